@@ -0,0 +1,85 @@
+//! Export of GPX data as CSV.
+
+use crate::AxisOrder;
+use std::io::Write;
+
+/// Quote a field if needed, according to RFC 4180.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Write the row of a point.
+/// `element` is the index of the track or route of the point, and `segment` the index of its segment (for track points).
+fn write_point(
+    writer: &mut impl Write,
+    kind: &str,
+    element: Option<usize>,
+    segment: Option<usize>,
+    index: usize,
+    point: &gpx::Waypoint,
+    order: AxisOrder,
+) -> eyre::Result<()> {
+    let [element, segment] =
+        [element, segment].map(|i| i.map(|i| i.to_string()).unwrap_or_default());
+
+    let time = match point.time {
+        Some(time) => time.format()?,
+        None => String::new(),
+    };
+
+    let [first, second] = order.arrange(point.point().y(), point.point().x());
+
+    writeln!(
+        writer,
+        "{kind},{element},{segment},{index},{first},{second},{},{time},{}",
+        point.elevation.map(|e| e.to_string()).unwrap_or_default(),
+        escape(point.name.as_deref().unwrap_or_default()),
+    )?;
+
+    Ok(())
+}
+
+/// Write GPX data as CSV, with one row per point.
+///
+/// The columns are: the kind of point (`trackpoint`, `routepoint` or `waypoint`),
+/// the index of its track or route, the index of its segment (for track points), the index of the point,
+/// the latitude and the longitude (in the given order), the elevation, the time and the name.
+/// With `trackpoints_only`, route points and waypoints are omitted.
+pub(crate) fn write_csv(
+    gpx: &gpx::Gpx,
+    writer: &mut impl Write,
+    trackpoints_only: bool,
+    order: AxisOrder,
+) -> eyre::Result<()> {
+    let [first, second] = order.arrange("lat", "lon");
+    writeln!(
+        writer,
+        "kind,element,segment,point,{first},{second},elevation,time,name"
+    )?;
+
+    for (t, track) in gpx.tracks.iter().enumerate() {
+        for (s, segment) in track.segments.iter().enumerate() {
+            for (p, point) in segment.points.iter().enumerate() {
+                write_point(writer, "trackpoint", Some(t), Some(s), p, point, order)?;
+            }
+        }
+    }
+
+    if !trackpoints_only {
+        for (r, route) in gpx.routes.iter().enumerate() {
+            for (p, point) in route.points.iter().enumerate() {
+                write_point(writer, "routepoint", Some(r), None, p, point, order)?;
+            }
+        }
+
+        for (p, point) in gpx.waypoints.iter().enumerate() {
+            write_point(writer, "waypoint", None, None, p, point, order)?;
+        }
+    }
+
+    Ok(())
+}
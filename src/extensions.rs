@@ -0,0 +1,509 @@
+//! Handling of the `<extensions>` elements of tracks.
+//!
+//! The `gpx` crate ignores extensions when reading and hence cannot write them back.
+//! This module reads them directly from the XML and inserts them again in the XML produced by the `gpx` crate.
+//! The other extensions (of the points, of the metadata...) are lost, but they can be detected to warn about it.
+//!
+//! It also inserts the speeds of the track points, which the `gpx` crate does not write, as extensions,
+//! and the copyright of the metadata, which it does not write either,
+//! and the location of the GPX schema, for the importers that validate the files against it.
+
+use eyre::eyre;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use xml::EmitterConfig;
+use xml::ParserConfig;
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent;
+
+/// The namespace of the XML Schema instance attributes, such as `xsi:schemaLocation`.
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// The location of the GPX 1.1 schema, as the pair of its namespace and of the URL of its XSD.
+const GPX_SCHEMA_LOCATION: &str =
+    "http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd";
+
+/// The content of the `<extensions>` element of a track, as raw XML events (including the element itself).
+#[derive(Clone, Debug)]
+pub(crate) struct TrackExtensions(Vec<XmlEvent>);
+
+impl TrackExtensions {
+    /// Get the display color of the track, if any.
+    ///
+    /// Both Garmin's `<gpxx:DisplayColor>` and gpx_style's `<gpx_style:color>` are supported.
+    pub(crate) fn display_color(&self) -> Option<String> {
+        let mut in_color = false;
+
+        for event in &self.0 {
+            match event {
+                XmlEvent::StartElement { name, .. } => {
+                    in_color = name.local_name == "DisplayColor" || name.local_name == "color";
+                }
+                XmlEvent::Characters(color) if in_color => return Some(color.trim().to_owned()),
+                _ => in_color = false,
+            }
+        }
+
+        None
+    }
+}
+
+fn parser_config() -> ParserConfig {
+    ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+}
+
+fn xml_error(err: impl std::fmt::Display) -> eyre::Report {
+    eyre!("Invalid XML: {err}")
+}
+
+/// Read the extensions of each track of a GPX document, in the order of the tracks.
+/// Tracks without extensions get `None`.
+pub(crate) fn read_track_extensions(
+    reader: impl Read,
+) -> eyre::Result<Vec<Option<TrackExtensions>>> {
+    let mut tracks_extensions = Vec::new();
+
+    // Depth of the current element: 1 for <gpx>, 2 for <trk>, 3 for the <extensions> of a track.
+    let mut depth = 0;
+    let mut in_track = false;
+    let mut current: Option<Vec<XmlEvent>> = None;
+
+    for event in parser_config().create_reader(reader) {
+        let event = event.map_err(xml_error)?;
+
+        match &event {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+
+                if depth == 2 && name.local_name == "trk" {
+                    in_track = true;
+                    tracks_extensions.push(None);
+                } else if depth == 3 && in_track && name.local_name == "extensions" {
+                    current = Some(Vec::new());
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                depth -= 1;
+
+                if depth == 1 {
+                    in_track = false;
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(events) = &mut current {
+            events.push(event);
+
+            if depth == 2 {
+                // The <extensions> element has just been closed.
+                let extensions = current.take().map(TrackExtensions);
+                *tracks_extensions
+                    .last_mut()
+                    .expect("Extensions are read inside a track") = extensions;
+            }
+        }
+    }
+
+    Ok(tracks_extensions)
+}
+
+/// Count the `<extensions>` elements of a GPX document that are not kept (i.e. not the ones of the tracks),
+/// by the name of their parent element (e.g. `trkpt` for the heart rate or the cadence of the points).
+pub(crate) fn count_dropped_extensions(reader: impl Read) -> eyre::Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+
+    // Names of the current element and of its ancestors.
+    let mut path: Vec<String> = Vec::new();
+
+    for event in parser_config().create_reader(reader) {
+        match event.map_err(xml_error)? {
+            XmlEvent::StartElement { name, .. } => {
+                if name.local_name == "extensions" {
+                    let parent = path.last().map(String::as_str).unwrap_or_default();
+                    let is_kept = path.len() == 2 && parent == "trk";
+                    if !is_kept {
+                        *counts.entry(parent.to_owned()).or_default() += 1;
+                    }
+                }
+                path.push(name.local_name);
+            }
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Copy the GPX document from `reader` to `writer`, inserting the extensions of each track.
+///
+/// `tracks_extensions` must be in the order of the tracks in the document.
+/// According to the GPX schema, the extensions are inserted just before the first segment of each track.
+/// The output is indented with `indent`.
+pub(crate) fn insert_track_extensions(
+    reader: impl Read,
+    writer: impl Write,
+    tracks_extensions: &[Option<TrackExtensions>],
+    indent: bool,
+) -> eyre::Result<()> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(indent)
+        .create_writer(writer);
+
+    let mut depth = 0;
+    let mut track_index = 0;
+    let mut pending: Option<&TrackExtensions> = None;
+
+    for event in parser_config().create_reader(reader) {
+        let event = event.map_err(xml_error)?;
+
+        match &event {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+
+                if depth == 2 && name.local_name == "trk" {
+                    pending = tracks_extensions.get(track_index).and_then(Option::as_ref);
+                    track_index += 1;
+                } else if depth == 3 && name.local_name == "trkseg" {
+                    write_extensions(&mut writer, pending.take())?;
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if depth == 2 {
+                    // A track without segments.
+                    write_extensions(&mut writer, pending.take())?;
+                }
+
+                depth -= 1;
+            }
+            _ => {}
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(xml_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The namespace of Garmin's `TrackPointExtension`, which has a `<speed>` element read by many tools.
+const TRACK_POINT_EXTENSION_NAMESPACE: &str =
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v2";
+
+/// Copy the GPX document from `reader` to `writer`, giving each track point its speed (in meters per second)
+/// in a `<gpxtpx:TrackPointExtension>`, at the end of the point as required by the GPX schema.
+///
+/// `speeds` must be in the order of the track points in the document; the points without speed get no extensions.
+/// The document must not already have extensions for the points, which is the case of the XML produced by the `gpx` crate.
+pub(crate) fn insert_point_speeds(
+    reader: impl Read,
+    writer: impl Write,
+    speeds: &[Option<f64>],
+) -> eyre::Result<()> {
+    use xml::writer::XmlEvent as WriterEvent;
+
+    let mut writer = EmitterConfig::new()
+        .perform_indent(false)
+        .create_writer(writer);
+
+    // Names of the current element and of its ancestors.
+    let mut path: Vec<String> = Vec::new();
+    let mut point_index = 0;
+
+    for event in parser_config().create_reader(reader) {
+        let mut event = event.map_err(xml_error)?;
+
+        match &mut event {
+            XmlEvent::StartElement {
+                name, namespace, ..
+            } => {
+                if path.is_empty() {
+                    namespace.put("gpxtpx", TRACK_POINT_EXTENSION_NAMESPACE);
+                }
+                path.push(name.local_name.clone());
+            }
+            XmlEvent::EndElement { .. } => {
+                if path == ["gpx", "trk", "trkseg", "trkpt"] {
+                    if let Some(speed) = speeds.get(point_index).copied().flatten() {
+                        let speed = format!("{speed:.2}");
+                        let events: [WriterEvent; 7] = [
+                            WriterEvent::start_element("extensions").into(),
+                            WriterEvent::start_element("gpxtpx:TrackPointExtension").into(),
+                            WriterEvent::start_element("gpxtpx:speed").into(),
+                            WriterEvent::characters(&speed),
+                            WriterEvent::end_element().into(),
+                            WriterEvent::end_element().into(),
+                            WriterEvent::end_element().into(),
+                        ];
+                        for event in events {
+                            writer.write(event).map_err(xml_error)?;
+                        }
+                    }
+                    point_index += 1;
+                }
+                path.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(xml_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_extensions<W: Write>(
+    writer: &mut xml::EventWriter<W>,
+    extensions: Option<&TrackExtensions>,
+) -> eyre::Result<()> {
+    for event in extensions.iter().flat_map(|extensions| &extensions.0) {
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(xml_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the GPX document from `reader` to `writer`, inserting the `<copyright>` element in its `<metadata>`,
+/// after the name, the description and the author, as required by the GPX schema.
+///
+/// The document must have a `<metadata>` element without copyright, which is the case of the XML produced
+/// by the `gpx` crate from data with metadata.
+pub(crate) fn insert_metadata_copyright(
+    reader: impl Read,
+    writer: impl Write,
+    copyright: &gpx::GpxCopyright,
+) -> eyre::Result<()> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(false)
+        .create_writer(writer);
+
+    let mut depth = 0;
+    let mut in_metadata = false;
+    let mut inserted = false;
+
+    for event in parser_config().create_reader(reader) {
+        let event = event.map_err(xml_error)?;
+
+        match &event {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+
+                if depth == 2 && name.local_name == "metadata" {
+                    in_metadata = true;
+                } else if depth == 3
+                    && in_metadata
+                    && !inserted
+                    && !["name", "desc", "author"].contains(&name.local_name.as_str())
+                {
+                    write_copyright(&mut writer, copyright)?;
+                    inserted = true;
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if depth == 2 && in_metadata {
+                    if !inserted {
+                        write_copyright(&mut writer, copyright)?;
+                        inserted = true;
+                    }
+                    in_metadata = false;
+                }
+
+                depth -= 1;
+            }
+            _ => {}
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(xml_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_copyright<W: Write>(
+    writer: &mut xml::EventWriter<W>,
+    copyright: &gpx::GpxCopyright,
+) -> eyre::Result<()> {
+    use xml::writer::XmlEvent as WriterEvent;
+
+    let mut start = WriterEvent::start_element("copyright");
+    if let Some(author) = &copyright.author {
+        start = start.attr("author", author);
+    }
+    writer.write(start).map_err(xml_error)?;
+
+    let year = copyright.year.map(|year| year.to_string());
+    for (name, value) in [("year", &year), ("license", &copyright.license)] {
+        if let Some(value) = value {
+            writer
+                .write(WriterEvent::start_element(name))
+                .map_err(xml_error)?;
+            writer
+                .write(WriterEvent::characters(value))
+                .map_err(xml_error)?;
+            writer
+                .write(WriterEvent::end_element())
+                .map_err(xml_error)?;
+        }
+    }
+
+    writer
+        .write(WriterEvent::end_element())
+        .map_err(xml_error)?;
+    Ok(())
+}
+
+/// Copy the GPX document from `reader` to `writer`, declaring the `xsi` namespace on the root `<gpx>` element
+/// and giving it the `xsi:schemaLocation` attribute of the GPX 1.1 schema (unless it already has one).
+pub(crate) fn insert_schema_location(reader: impl Read, writer: impl Write) -> eyre::Result<()> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(false)
+        .create_writer(writer);
+
+    let mut is_root = true;
+
+    for event in parser_config().create_reader(reader) {
+        let mut event = event.map_err(xml_error)?;
+
+        if let XmlEvent::StartElement {
+            attributes,
+            namespace,
+            ..
+        } = &mut event
+            && is_root
+        {
+            is_root = false;
+
+            let has_schema_location = attributes.iter().any(|attribute| {
+                attribute.name.local_name == "schemaLocation"
+                    && attribute.name.namespace.as_deref() == Some(XSI_NAMESPACE)
+            });
+            if !has_schema_location {
+                namespace.put("xsi", XSI_NAMESPACE);
+                attributes.push(OwnedAttribute::new(
+                    OwnedName::qualified("schemaLocation", XSI_NAMESPACE, Some("xsi")),
+                    GPX_SCHEMA_LOCATION,
+                ));
+            }
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(xml_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixture;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    /// Describe the extensions of the tracks by the names (with their namespaces) of their elements,
+    /// their attributes and their texts, which is what matters to the tools reading them
+    /// (unlike the elements where the namespaces are declared, for instance).
+    fn describe(tracks_extensions: &[Option<TrackExtensions>]) -> Vec<Option<Vec<String>>> {
+        let describe_name = |name: &xml::name::OwnedName| {
+            format!(
+                "{{{}}}{}",
+                name.namespace.as_deref().unwrap_or_default(),
+                name.local_name
+            )
+        };
+
+        tracks_extensions
+            .iter()
+            .map(|extensions| {
+                extensions.as_ref().map(|extensions| {
+                    extensions
+                        .0
+                        .iter()
+                        .filter_map(|event| match event {
+                            XmlEvent::StartElement {
+                                name, attributes, ..
+                            } => {
+                                let attributes = attributes
+                                    .iter()
+                                    .map(|attribute| {
+                                        format!(
+                                            " {}=\"{}\"",
+                                            describe_name(&attribute.name),
+                                            attribute.value
+                                        )
+                                    })
+                                    .collect::<String>();
+                                Some(format!("<{}{attributes}>", describe_name(name)))
+                            }
+                            XmlEvent::EndElement { name } => {
+                                Some(format!("</{}>", describe_name(name)))
+                            }
+                            XmlEvent::Characters(text) => Some(text.clone()),
+                            _ => None,
+                        })
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    /// Read a file, write it back (with the extensions of its tracks) and read the extensions of what was written.
+    fn round_trip(name: &str) -> (Vec<Option<TrackExtensions>>, Vec<Option<TrackExtensions>>) {
+        let file = fixture(name);
+        let original = read_track_extensions(BufReader::new(File::open(&file).unwrap())).unwrap();
+        let gpx = crate::read_gpx(BufReader::new(File::open(&file).unwrap())).unwrap();
+
+        let mut buffer = Vec::new();
+        crate::write_gpx_with_track_extensions(&gpx, &original, &mut buffer, &Default::default())
+            .unwrap();
+        let written = read_track_extensions(buffer.as_slice()).unwrap();
+
+        (original, written)
+    }
+
+    #[test]
+    fn garmin_track_extensions_survive_a_round_trip() {
+        let (original, written) = round_trip("garmin.gpx");
+
+        assert_eq!(describe(&written), describe(&original));
+        assert_eq!(
+            written[0].as_ref().and_then(TrackExtensions::display_color),
+            Some("Magenta".to_owned())
+        );
+    }
+
+    #[test]
+    fn wahoo_track_extensions_survive_a_round_trip() {
+        let (original, written) = round_trip("wahoo.gpx");
+
+        assert_eq!(describe(&written), describe(&original));
+        assert!(written[0].is_some());
+        assert!(written[1].is_none());
+        assert_eq!(
+            written[0].as_ref().and_then(TrackExtensions::display_color),
+            Some("0000FF".to_owned())
+        );
+    }
+
+    #[test]
+    fn extensions_of_points_are_counted_as_dropped() {
+        let file = fixture("wahoo.gpx");
+        let counts = count_dropped_extensions(File::open(file).unwrap()).unwrap();
+
+        assert_eq!(counts, BTreeMap::from([("trkpt".to_owned(), 2)]));
+    }
+}
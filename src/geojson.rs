@@ -0,0 +1,193 @@
+//! Conversions between GPX data and GeoJSON documents.
+
+use crate::AxisOrder;
+use eyre::eyre;
+use serde_json::{Map, Value, json};
+
+/// Convert a position (`[lon, lat]` or `[lon, lat, elevation]`) to a GPX point.
+fn position_to_waypoint(position: &Value) -> eyre::Result<gpx::Waypoint> {
+    let coordinates = position
+        .as_array()
+        .ok_or_else(|| eyre!("A position should be an array, got '{position}'"))?;
+
+    let get = |index: usize| coordinates.get(index).and_then(Value::as_f64);
+
+    let (Some(lon), Some(lat)) = (get(0), get(1)) else {
+        return Err(eyre!(
+            "A position should start with a longitude and a latitude, got '{position}'"
+        ));
+    };
+
+    let mut waypoint = gpx::Waypoint::new((lon, lat).into());
+    waypoint.elevation = get(2);
+    Ok(waypoint)
+}
+
+/// Convert an array of positions to a track segment.
+fn positions_to_segment(positions: &Value) -> eyre::Result<gpx::TrackSegment> {
+    let points = positions
+        .as_array()
+        .ok_or_else(|| eyre!("A line should be an array of positions"))?
+        .iter()
+        .map(position_to_waypoint)
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    Ok(gpx::TrackSegment { points })
+}
+
+/// Convert a GeoJSON `FeatureCollection` to GPX data.
+///
+/// `LineString` and `MultiLineString` features become tracks, `Point` features become waypoints.
+/// The `name` property of the features is used as the name of the tracks and waypoints.
+/// Other geometries are ignored, with a warning.
+pub(crate) fn feature_collection_to_gpx(document: &Value) -> eyre::Result<gpx::Gpx> {
+    if document["type"] != "FeatureCollection" {
+        return Err(eyre!("The GeoJSON document should be a FeatureCollection"));
+    }
+
+    let features = document["features"]
+        .as_array()
+        .ok_or_else(|| eyre!("The FeatureCollection has no 'features' array"))?;
+
+    let mut gpx = gpx::Gpx {
+        version: gpx::GpxVersion::Gpx11,
+        ..Default::default()
+    };
+
+    for (i, feature) in features.iter().enumerate() {
+        let name = feature["properties"]["name"].as_str().map(str::to_owned);
+        let geometry = &feature["geometry"];
+        let coordinates = &geometry["coordinates"];
+
+        match geometry["type"].as_str() {
+            Some("Point") => {
+                let mut waypoint = position_to_waypoint(coordinates)?;
+                waypoint.name = name;
+                gpx.waypoints.push(waypoint);
+            }
+            Some("LineString") => {
+                gpx.tracks.push(gpx::Track {
+                    name,
+                    segments: vec![positions_to_segment(coordinates)?],
+                    ..Default::default()
+                });
+            }
+            Some("MultiLineString") => {
+                let segments = coordinates
+                    .as_array()
+                    .ok_or_else(|| eyre!("A MultiLineString should be an array of lines"))?
+                    .iter()
+                    .map(positions_to_segment)
+                    .collect::<eyre::Result<Vec<_>>>()?;
+
+                gpx.tracks.push(gpx::Track {
+                    name,
+                    segments,
+                    ..Default::default()
+                });
+            }
+            other => {
+                println!(
+                    "Warning: ignoring feature #{i} since its geometry is not supported ({})",
+                    other.unwrap_or("none")
+                );
+            }
+        }
+    }
+
+    Ok(gpx)
+}
+
+/// Convert a GPX point to a GeoJSON position (`[lon, lat]` or `[lon, lat, elevation]`, unless `order` says otherwise).
+fn waypoint_to_position(waypoint: &gpx::Waypoint, order: AxisOrder) -> Value {
+    let [first, second] = order.arrange(waypoint.point().y(), waypoint.point().x());
+    match waypoint.elevation {
+        Some(elevation) => json!([first, second, elevation]),
+        None => json!([first, second]),
+    }
+}
+
+fn points_to_positions(points: &[gpx::Waypoint], order: AxisOrder) -> Value {
+    points
+        .iter()
+        .map(|point| waypoint_to_position(point, order))
+        .collect()
+}
+
+fn feature(kind: &str, name: &Option<String>, geometry: Value) -> Value {
+    let mut properties = Map::new();
+    properties.insert("kind".to_owned(), json!(kind));
+    if let Some(name) = name {
+        properties.insert("name".to_owned(), json!(name));
+    }
+
+    json!({
+        "type": "Feature",
+        "properties": properties,
+        "geometry": geometry,
+    })
+}
+
+/// Convert GPX data to a GeoJSON `FeatureCollection`.
+///
+/// Tracks become `MultiLineString` features (one line per segment), routes become `LineString` features,
+/// and waypoints become `Point` features. Each feature has a `kind` property (`track`, `route` or `waypoint`)
+/// and a `name` property if the GPX element has a name.
+/// The collection itself is named after the name of the GPX data, if any.
+/// With `trackpoints_only`, routes and waypoints are omitted.
+/// The GeoJSON specification requires `AxisOrder::LonLat`, but some tools expect the other order.
+pub(crate) fn gpx_to_feature_collection(
+    gpx: &gpx::Gpx,
+    trackpoints_only: bool,
+    order: AxisOrder,
+) -> Value {
+    let mut features = Vec::new();
+
+    for track in &gpx.tracks {
+        let lines = track
+            .segments
+            .iter()
+            .map(|segment| points_to_positions(&segment.points, order))
+            .collect::<Vec<_>>();
+
+        features.push(feature(
+            "track",
+            &track.name,
+            json!({ "type": "MultiLineString", "coordinates": lines }),
+        ));
+    }
+
+    if !trackpoints_only {
+        for route in &gpx.routes {
+            features.push(feature(
+                "route",
+                &route.name,
+                json!({ "type": "LineString", "coordinates": points_to_positions(&route.points, order) }),
+            ));
+        }
+
+        for waypoint in &gpx.waypoints {
+            features.push(feature(
+                "waypoint",
+                &waypoint.name,
+                json!({ "type": "Point", "coordinates": waypoint_to_position(waypoint, order) }),
+            ));
+        }
+    }
+
+    let mut document = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    if let Some(name) = gpx
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.name.as_ref())
+    {
+        // Not in the GeoJSON specification, but GDAL and QGIS use it as the name of the layer.
+        document["name"] = json!(name);
+    }
+
+    document
+}
@@ -0,0 +1,48 @@
+//! Download of GPX files given by HTTP(S) URLs, with the `http` feature.
+
+use eyre::eyre;
+
+/// The content types accepted for GPX files.
+/// Servers rarely know `application/gpx+xml` and often serve GPX files as generic XML or binary data.
+const CONTENT_TYPES: [&str; 4] = [
+    "application/gpx+xml",
+    "application/xml",
+    "text/xml",
+    "application/octet-stream",
+];
+
+/// Download the content of a file, checking that the server tells it is a GPX (or XML) file.
+/// A missing content type is accepted, since the extension of the URL has already been checked.
+pub(crate) fn download(url: &str) -> eyre::Result<Vec<u8>> {
+    let response = reqwest::blocking::get(url)
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .map_err(|err| eyre!("Cannot download '{url}': {err}"))?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            // Ignore the parameters, e.g. "text/xml; charset=utf-8".
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+
+    if let Some(content_type) = content_type
+        && !CONTENT_TYPES.contains(&content_type.as_str())
+    {
+        return Err(eyre!(
+            "'{url}' does not appear to be a GPX file (since its content type is '{content_type}')"
+        ));
+    }
+
+    let content = response
+        .bytes()
+        .map_err(|err| eyre!("Cannot download '{url}': {err}"))?;
+
+    Ok(content.to_vec())
+}
@@ -0,0 +1,286 @@
+//! Export of GPX data as KML.
+
+use crate::{ColorBy, distance};
+use std::io::Write;
+use time::OffsetDateTime;
+use xml::EmitterConfig;
+use xml::writer::{EventWriter, XmlEvent};
+
+fn write_event<'a, W: Write>(
+    writer: &mut EventWriter<W>,
+    event: impl Into<XmlEvent<'a>>,
+) -> eyre::Result<()> {
+    writer
+        .write(event)
+        .map_err(|err| eyre::eyre!("Cannot write KML: {err}"))
+}
+
+fn write_text_element<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &str,
+    text: &str,
+) -> eyre::Result<()> {
+    write_event(writer, XmlEvent::start_element(name))?;
+    write_event(writer, XmlEvent::characters(text))?;
+    write_event(writer, XmlEvent::end_element())
+}
+
+/// Format points as the content of a KML `<coordinates>` element.
+fn format_coordinates(points: &[gpx::Waypoint]) -> String {
+    points
+        .iter()
+        .map(|point| {
+            let (lon, lat) = (point.point().x(), point.point().y());
+            match point.elevation {
+                Some(elevation) => format!("{lon},{lat},{elevation}"),
+                None => format!("{lon},{lat}"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_geometry<W: Write>(
+    writer: &mut EventWriter<W>,
+    geometry: &str,
+    points: &[gpx::Waypoint],
+) -> eyre::Result<()> {
+    write_event(writer, XmlEvent::start_element(geometry))?;
+    write_text_element(writer, "coordinates", &format_coordinates(points))?;
+    write_event(writer, XmlEvent::end_element())
+}
+
+fn start_placemark<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &Option<String>,
+) -> eyre::Result<()> {
+    write_event(writer, XmlEvent::start_element("Placemark"))?;
+    if let Some(name) = name {
+        write_text_element(writer, "name", name)?;
+    }
+    Ok(())
+}
+
+/// How to color the lines of the tracks: by bands of equal width between the minimum and the maximum of a metric.
+pub(crate) struct Coloring {
+    pub(crate) by: ColorBy,
+    pub(crate) bands: usize,
+}
+
+/// Value of the metric for the line between two consecutive points, if it can be computed.
+/// The speed is in km/h, and the elevation is the mean of the elevations of the points (in meters).
+fn compute_metric(by: ColorBy, a: &gpx::Waypoint, b: &gpx::Waypoint) -> Option<f64> {
+    match by {
+        ColorBy::Speed => {
+            let duration = OffsetDateTime::from(b.time?) - OffsetDateTime::from(a.time?);
+            let seconds = duration.as_seconds_f64();
+            (seconds > 0.0).then(|| distance(a, b) / seconds * 3.6)
+        }
+        ColorBy::Elevation => Some((a.elevation? + b.elevation?) / 2.0),
+    }
+}
+
+/// Compute the minimum and the maximum of the metric over all the tracks, if it can be computed at all.
+fn compute_metric_range(gpx: &gpx::Gpx, by: ColorBy) -> Option<(f64, f64)> {
+    gpx.tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| segment.points.windows(2))
+        .filter_map(|pair| compute_metric(by, &pair[0], &pair[1]))
+        .fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((f64::min(min, value), f64::max(max, value))),
+        })
+}
+
+/// Bands of values of a metric, from `min` to `max`.
+struct Bands {
+    by: ColorBy,
+    count: usize,
+    min: f64,
+    max: f64,
+}
+
+impl Bands {
+    /// Get the band of a value, or `count` (the "no data" band) if there is no value.
+    fn get(&self, value: Option<f64>) -> usize {
+        match value {
+            None => self.count,
+            Some(_) if self.max <= self.min => 0,
+            Some(value) => {
+                let ratio = (value - self.min) / (self.max - self.min);
+                ((ratio * self.count as f64) as usize).min(self.count - 1)
+            }
+        }
+    }
+
+    fn label(&self, band: usize) -> String {
+        if band == self.count {
+            return "n/a".to_owned();
+        }
+
+        let width = (self.max - self.min) / self.count as f64;
+        let (low, high) = (
+            self.min + width * band as f64,
+            self.min + width * (band + 1) as f64,
+        );
+        match self.by {
+            ColorBy::Speed => format!("{low:.1}-{high:.1} km/h"),
+            ColorBy::Elevation => format!("{low:.0}-{high:.0} m"),
+        }
+    }
+
+    /// Get the color of a band, as KML writes them (`aabbggrr`):
+    /// from green for the lowest band to red for the highest, and grey for "no data".
+    fn color(&self, band: usize) -> String {
+        if band == self.count {
+            return "ff808080".to_owned();
+        }
+
+        let ratio = if self.count > 1 {
+            band as f64 / (self.count - 1) as f64
+        } else {
+            0.0
+        };
+        let red = (255.0 * ratio).round() as u8;
+        let green = (255.0 * (1.0 - ratio)).round() as u8;
+        format!("ff00{green:02x}{red:02x}")
+    }
+}
+
+fn write_styles<W: Write>(writer: &mut EventWriter<W>, bands: &Bands) -> eyre::Result<()> {
+    for band in 0..=bands.count {
+        let id = format!("band-{band}");
+        write_event(writer, XmlEvent::start_element("Style").attr("id", &id))?;
+        write_event(writer, XmlEvent::start_element("LineStyle"))?;
+        write_text_element(writer, "color", &bands.color(band))?;
+        write_text_element(writer, "width", "4")?;
+        write_event(writer, XmlEvent::end_element())?;
+        write_event(writer, XmlEvent::end_element())?;
+    }
+    Ok(())
+}
+
+/// Write a track as one placemark per band, each with the parts of the segments whose lines are in this band.
+fn write_colored_track<W: Write>(
+    writer: &mut EventWriter<W>,
+    track: &gpx::Track,
+    bands: &Bands,
+) -> eyre::Result<()> {
+    // The parts of the segments in each band, the last band being "no data".
+    let mut bands_lines: Vec<Vec<&[gpx::Waypoint]>> = vec![Vec::new(); bands.count + 1];
+
+    for segment in &track.segments {
+        let points = &segment.points;
+        let line_bands = points
+            .windows(2)
+            .map(|pair| bands.get(compute_metric(bands.by, &pair[0], &pair[1])))
+            .collect::<Vec<_>>();
+
+        // Consecutive lines in the same band are drawn together, sharing their points.
+        let mut start = 0;
+        for i in 1..=line_bands.len() {
+            if i == line_bands.len() || line_bands[i] != line_bands[start] {
+                bands_lines[line_bands[start]].push(&points[start..=i]);
+                start = i;
+            }
+        }
+    }
+
+    for (band, lines) in bands_lines.iter().enumerate() {
+        if lines.is_empty() {
+            continue;
+        }
+
+        let label = bands.label(band);
+        let name = match &track.name {
+            Some(name) => format!("{name} ({label})"),
+            None => label,
+        };
+        start_placemark(writer, &Some(name))?;
+        write_text_element(writer, "styleUrl", &format!("#band-{band}"))?;
+        write_event(writer, XmlEvent::start_element("MultiGeometry"))?;
+        for line in lines {
+            write_geometry(writer, "LineString", line)?;
+        }
+        write_event(writer, XmlEvent::end_element())?;
+        write_event(writer, XmlEvent::end_element())?;
+    }
+
+    Ok(())
+}
+
+/// Write GPX data as a KML document.
+///
+/// Tracks become placemarks with a `<MultiGeometry>` of `<LineString>`s (one per segment),
+/// routes become placemarks with a `<LineString>`, and waypoints become placemarks with a `<Point>`.
+/// With a `coloring`, each track becomes a placemark per band instead, with a style setting the color of its lines.
+pub(crate) fn write_kml(
+    gpx: &gpx::Gpx,
+    writer: impl Write,
+    name: Option<&str>,
+    coloring: Option<&Coloring>,
+) -> eyre::Result<()> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+
+    write_event(
+        &mut writer,
+        XmlEvent::start_element("kml").default_ns("http://www.opengis.net/kml/2.2"),
+    )?;
+    write_event(&mut writer, XmlEvent::start_element("Document"))?;
+    if let Some(name) = name {
+        write_text_element(&mut writer, "name", name)?;
+    }
+
+    let bands = coloring.map(|coloring| {
+        let (min, max) = compute_metric_range(gpx, coloring.by).unwrap_or_else(|| {
+            println!(
+                "Warning: the {} cannot be computed, the tracks are not colored",
+                coloring.by
+            );
+            (0.0, 0.0)
+        });
+        Bands {
+            by: coloring.by,
+            count: coloring.bands,
+            min,
+            max,
+        }
+    });
+
+    if let Some(bands) = &bands {
+        write_styles(&mut writer, bands)?;
+    }
+
+    for track in &gpx.tracks {
+        if let Some(bands) = &bands {
+            write_colored_track(&mut writer, track, bands)?;
+            continue;
+        }
+
+        start_placemark(&mut writer, &track.name)?;
+        write_event(&mut writer, XmlEvent::start_element("MultiGeometry"))?;
+        for segment in &track.segments {
+            write_geometry(&mut writer, "LineString", &segment.points)?;
+        }
+        write_event(&mut writer, XmlEvent::end_element())?;
+        write_event(&mut writer, XmlEvent::end_element())?;
+    }
+
+    for route in &gpx.routes {
+        start_placemark(&mut writer, &route.name)?;
+        write_geometry(&mut writer, "LineString", &route.points)?;
+        write_event(&mut writer, XmlEvent::end_element())?;
+    }
+
+    for waypoint in &gpx.waypoints {
+        start_placemark(&mut writer, &waypoint.name)?;
+        write_geometry(&mut writer, "Point", std::slice::from_ref(waypoint))?;
+        write_event(&mut writer, XmlEvent::end_element())?;
+    }
+
+    write_event(&mut writer, XmlEvent::end_element())?;
+    write_event(&mut writer, XmlEvent::end_element())
+}
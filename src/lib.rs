@@ -1,11 +1,28 @@
 use eyre::eyre;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::iter::zip;
 use std::path::{Path, PathBuf};
-use strum_macros::Display;
+use strum_macros::{Display, EnumString, VariantNames};
+use time::OffsetDateTime;
+use xml::EmitterConfig;
+
+mod csv;
+mod extensions;
+mod geojson;
+#[cfg(feature = "http")]
+mod http;
+mod kml;
+mod profile;
+pub mod transform;
+
+use extensions::TrackExtensions;
+use transform::Transform;
 
 /// Check if the path denoted by `directory` is actually an existing directory.
 fn check_directory(directory: &impl AsRef<Path>) -> eyre::Result<()> {
@@ -21,9 +38,45 @@ fn check_directory(directory: &impl AsRef<Path>) -> eyre::Result<()> {
     }
 }
 
-/// Check if the path denoted by `files` are actually an existing files.
+/// Read the paths listed in a file, one per line, e.g. when they are too many for the command-line.
+/// Blank lines and lines starting with `#` are ignored, as are the spaces around the paths.
+/// The relative paths are relative to the directory of the list, not to the current directory.
+pub fn read_input_list(list: &impl AsRef<Path>) -> eyre::Result<Vec<PathBuf>> {
+    let list = list.as_ref();
+    let content = std::fs::read_to_string(list)
+        .map_err(|err| eyre!("Cannot read the input list '{}': {err}", list.display()))?;
+    let directory = list.parent().unwrap_or(Path::new(""));
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match as_url(Path::new(line)) {
+            Some(_) => PathBuf::from(line),
+            // An absolute path replaces the directory.
+            None => directory.join(line),
+        })
+        .collect())
+}
+
+/// Check if the path denoted by `files` are actually an existing GPX files.
 /// It also checks that there is no duplicates in `files`.
 fn check_files(files: &[impl AsRef<Path>]) -> eyre::Result<()> {
+    check_files_of_format(files, "GPX", &["gpx"])
+}
+
+/// Check if a file has an extension, ignoring the case: some devices export files like `RIDE.GPX`.
+fn has_extension(file: &Path, extension: &str) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Same as `check_files()` for files of another format, recognized by their `extensions`.
+fn check_files_of_format(
+    files: &[impl AsRef<Path>],
+    format: &str,
+    extensions: &[&str],
+) -> eyre::Result<()> {
     // It's OK if `files` is empty.
     // Clap verifies that the list passed on the CLI is not empty so it's unlikely to get an empty list here.
 
@@ -36,17 +89,22 @@ fn check_files(files: &[impl AsRef<Path>]) -> eyre::Result<()> {
     for file in files {
         let file = file.as_ref();
 
-        if !file.is_file() {
-            return Err(eyre!(
-                "'{}' does not exist or is a directory",
-                file.display()
-            ));
-        }
+        let name = match as_url(file) {
+            Some(url) => Path::new(strip_url_query(url)),
+            None if file.is_file() => file,
+            None => {
+                return Err(eyre!(
+                    "'{}' does not exist or is a directory",
+                    file.display()
+                ));
+            }
+        };
 
-        if file.extension() != Some("gpx".as_ref()) {
+        if !extensions.iter().any(|ext| has_extension(name, ext)) {
             return Err(eyre!(
-                "'{}' does not appear to be a GPX file (since its extension is not '.gpx')",
-                file.display()
+                "'{}' does not appear to be a {format} file (since its extension is not '.{}')",
+                file.display(),
+                extensions.join("' or '.")
             ));
         }
     }
@@ -63,9 +121,203 @@ fn check_files(files: &[impl AsRef<Path>]) -> eyre::Result<()> {
     Ok(())
 }
 
-/// List the GPX files in a directory, based on the extensions.
-fn list_gpx_files(directory: &impl AsRef<Path>) -> eyre::Result<Vec<PathBuf>> {
+/// Tell whether no GPX files were found in a directory by a "*-all" command, in which case there is nothing to do.
+/// This is fine for interactive use, but with `error_on_empty` it is an error, so that scripts can stop.
+fn is_empty_directory(
+    files: &[PathBuf],
+    directory: &impl AsRef<Path>,
+    error_on_empty: bool,
+) -> eyre::Result<bool> {
+    if !files.is_empty() {
+        return Ok(false);
+    }
+
+    let message = format!("No GPX files found in '{}'", directory.as_ref().display());
+    if error_on_empty {
+        return Err(eyre!(message));
+    }
+
+    println!("{message}");
+    Ok(true)
+}
+
+/// Filters on the names of the files listed in a directory by the "*-all" commands.
+#[derive(Default)]
+pub struct FileFilter {
+    /// Keep only the files whose names match at least one of these glob patterns (if any).
+    pub include: Vec<String>,
+    /// Discard the files whose names match at least one of these glob patterns.
+    pub exclude: Vec<String>,
+}
+
+impl FileFilter {
+    fn compile(patterns: &[String]) -> eyre::Result<Vec<glob::Pattern>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|err| eyre!("Invalid glob pattern '{pattern}': {err}"))
+            })
+            .collect()
+    }
+
+    /// Get a predicate telling if a file is accepted by this filter.
+    fn to_predicate(&self) -> eyre::Result<impl Fn(&Path) -> bool> {
+        let include = Self::compile(&self.include)?;
+        let exclude = Self::compile(&self.exclude)?;
+
+        Ok(move |path: &Path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let matches = |pattern: &glob::Pattern| pattern.matches(&name);
+
+            (include.is_empty() || include.iter().any(matches)) && !exclude.iter().any(matches)
+        })
+    }
+}
+
+/// The orders in which the commands can process files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum FileOrder {
+    /// By the bytes of the names, so that the order is the same on all systems, whatever the locale.
+    /// Uppercase letters come before lowercase letters, and "file10" comes before "file2".
+    #[default]
+    Name,
+    /// By the names, comparing the sequences of digits as numbers: "file2" comes before "file10".
+    Natural,
+    /// By the modification times of the files, then by their names for the files modified at the same time.
+    Mtime,
+    /// By the times of the first points of the tracks, then by the names of the files for equal times.
+    /// Files without times come last.
+    Time,
+}
+
+/// How to sort files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSort {
+    /// `None` keeps the given order (or uses the order of the names, for the files listed in a directory).
+    pub by: Option<FileOrder>,
+    /// Reverse the order.
+    pub reverse: bool,
+}
+
+/// Compare two strings, comparing the sequences of digits they contain as numbers.
+/// Strings that only differ by leading zeros are compared by their bytes, so the order is total.
+fn compare_naturally(a: &str, b: &str) -> Ordering {
+    fn split_chunks(s: &str) -> Vec<&str> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut was_digit = None;
+
+        for (i, c) in s.char_indices() {
+            let is_digit = c.is_ascii_digit();
+            if was_digit.is_some_and(|was_digit| was_digit != is_digit) {
+                chunks.push(&s[start..i]);
+                start = i;
+            }
+            was_digit = Some(is_digit);
+        }
+
+        if !s.is_empty() {
+            chunks.push(&s[start..]);
+        }
+
+        chunks
+    }
+
+    let compare_chunks = |a: &str, b: &str| {
+        let is_number = |chunk: &str| chunk.starts_with(|c: char| c.is_ascii_digit());
+
+        if is_number(a) && is_number(b) {
+            let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+            // Numbers without leading zeros: the longer, the greater.
+            a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+        } else {
+            a.cmp(b)
+        }
+    };
+
+    let (chunks_a, chunks_b) = (split_chunks(a), split_chunks(b));
+
+    zip(&chunks_a, &chunks_b)
+        .map(|(a, b)| compare_chunks(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| chunks_a.len().cmp(&chunks_b.len()))
+        .then_with(|| a.cmp(b))
+}
+
+/// Sort items by the keys computed by `get_key`, which may fail.
+fn sort_by_fallible_key<T, K: Ord>(
+    items: &mut Vec<T>,
+    get_key: impl Fn(&T) -> eyre::Result<K>,
+) -> eyre::Result<()> {
+    let mut keyed_items = items
+        .drain(..)
+        .map(|item| Ok((get_key(&item)?, item)))
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    keyed_items.sort_by(|(a, _), (b, _)| a.cmp(b));
+    items.extend(keyed_items.into_iter().map(|(_, item)| item));
+    Ok(())
+}
+
+/// Sort items related to files (the paths themselves, or whatever was loaded from them) according to `sort`.
+/// `get_start_time` is only called when sorting by time.
+fn sort_files<T>(
+    items: &mut Vec<T>,
+    sort: FileSort,
+    get_path: fn(&T) -> &Path,
+    get_start_time: impl Fn(&T) -> eyre::Result<Option<OffsetDateTime>>,
+) -> eyre::Result<()> {
+    let get_name = |item: &T| get_path(item).file_name().unwrap_or_default().to_owned();
+
+    match sort.by {
+        None => {}
+        Some(FileOrder::Name) => items.sort_by_key(|item| get_name(item).into_encoded_bytes()),
+        Some(FileOrder::Natural) => items.sort_by(|a, b| {
+            compare_naturally(
+                &get_name(a).to_string_lossy(),
+                &get_name(b).to_string_lossy(),
+            )
+        }),
+        Some(FileOrder::Mtime) => sort_by_fallible_key(items, |item| {
+            let path = get_path(item);
+            let mtime = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|err| {
+                    eyre!(
+                        "Cannot get the modification time of '{}': {err}",
+                        path.display()
+                    )
+                })?;
+            Ok((mtime, get_name(item).into_encoded_bytes()))
+        })?,
+        Some(FileOrder::Time) => sort_by_fallible_key(items, |item| {
+            let start_time = get_start_time(item)?;
+            Ok((
+                start_time.is_none(),
+                start_time,
+                get_name(item).into_encoded_bytes(),
+            ))
+        })?,
+    }
+
+    if sort.reverse {
+        items.reverse();
+    }
+
+    Ok(())
+}
+
+/// List the GPX files in a directory, based on the extensions, and keep those accepted by `filter`.
+/// The files are sorted according to `sort` (by name by default).
+fn list_gpx_files(
+    directory: &impl AsRef<Path>,
+    filter: &FileFilter,
+    sort: FileSort,
+) -> eyre::Result<Vec<PathBuf>> {
     assert!(directory.as_ref().is_dir());
+    let is_accepted = filter.to_predicate()?;
 
     let mut gpx_files: Vec<PathBuf> = std::fs::read_dir(directory)
         .map_err(|err| {
@@ -78,7 +330,7 @@ fn list_gpx_files(directory: &impl AsRef<Path>) -> eyre::Result<Vec<PathBuf>> {
             match res {
                 Ok(dir_entry) => {
                     let path = dir_entry.path();
-                    if path.extension().is_some_and(|ext| ext == "gpx") {
+                    if has_extension(&path, "gpx") && is_accepted(&path) {
                         Some(path) // accept file
                     } else {
                         None // reject it
@@ -92,299 +344,5064 @@ fn list_gpx_files(directory: &impl AsRef<Path>) -> eyre::Result<Vec<PathBuf>> {
         })
         .collect();
 
-    gpx_files.sort();
+    let sort = FileSort {
+        by: Some(sort.by.unwrap_or_default()),
+        ..sort
+    };
+    sort_files(&mut gpx_files, sort, PathBuf::as_path, |path| {
+        Ok(get_start_time(&read_gpx_file(
+            path,
+            &ReadOptions::default(),
+        )?))
+    })?;
 
     Ok(gpx_files)
 }
 
-/// Load GPX data from a file.
-fn load_gpx(file: &impl AsRef<Path>) -> eyre::Result<gpx::Gpx> {
-    assert!(file.as_ref().extension().is_some_and(|ext| ext == "gpx"));
-    println!("Loading GPX from '{}'...", file.as_ref().display());
-
-    let f = File::open(file)?;
-    let reader = BufReader::new(f);
-    let gpx = gpx::read(reader)?;
-    Ok(gpx)
+/// Options for reading GPX files, shared by all the commands.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+    /// Check the structure of every file read and fail if it is broken,
+    /// instead of processing files that the lenient parser of the `gpx` crate accepted:
+    /// the coordinates must be valid (see `has_valid_coordinates()`), there must be tracks, routes or waypoints,
+    /// and the version of GPX must be known.
+    pub strict: bool,
 }
 
-/// Save GPX data to a file.
-fn save_gpx(gpx: &gpx::Gpx, file: &impl AsRef<Path>) -> eyre::Result<()> {
-    assert!(file.as_ref().extension().is_some_and(|ext| ext == "gpx"));
-    println!("Saving GPX to '{}'...", file.as_ref().display());
+/// Check the structure of GPX data read from `file`, if `options` ask for it.
+fn check_structure(
+    gpx: &gpx::Gpx,
+    file: &impl AsRef<Path>,
+    options: &ReadOptions,
+) -> eyre::Result<()> {
+    if !options.strict {
+        return Ok(());
+    }
+
+    let file = file.as_ref().display();
+
+    let invalid_points = find_invalid_points(gpx);
+    if let Some(point) = invalid_points.first() {
+        return Err(eyre!(
+            "'{file}' has {} point(s) with invalid coordinates (e.g. {point})",
+            invalid_points.len()
+        ));
+    }
+
+    if gpx.tracks.is_empty() && gpx.routes.is_empty() && gpx.waypoints.is_empty() {
+        return Err(eyre!("'{file}' has no tracks, routes or waypoints"));
+    }
+
+    if gpx.version == gpx::GpxVersion::Unknown {
+        return Err(eyre!("'{file}' has no known GPX version"));
+    }
 
-    let f = File::create(file)?;
-    let writer = BufWriter::new(f);
-    gpx::write(gpx, writer)?;
     Ok(())
 }
 
-/// Get the value to use for the "creator" field for files we create.
-fn get_creator() -> String {
-    format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
-}
+/// Load GPX data from a file (or from a URL, see `read_gpx_file()`).
+fn load_gpx(file: &impl AsRef<Path>, read_options: &ReadOptions) -> eyre::Result<gpx::Gpx> {
+    println!("Loading GPX from '{}'...", file.as_ref().display());
 
-#[derive(Display)]
-enum Action {
-    #[strum(serialize = "decimated-by-{0}")]
-    Decimate(u16),
-    #[strum(serialize = "inverted")]
-    Invert,
-    #[strum(serialize = "merged")]
-    Merge,
+    let gpx = match as_url(file.as_ref()) {
+        Some(url) => {
+            // Downloaded once, for both the data and the extensions.
+            let content = download(url)?;
+            warn_if_extensions_are_dropped(file, content.as_slice())?;
+            let gpx = read_gpx(content.as_slice())?;
+            check_structure(&gpx, file, read_options)?;
+            gpx
+        }
+        None => {
+            assert!(has_extension(file.as_ref(), "gpx"));
+            warn_if_extensions_are_dropped(file, BufReader::new(File::open(file)?))?;
+            read_gpx_file(file, read_options)?
+        }
+    };
+
+    let invalid_points = find_invalid_points(&gpx);
+    if !invalid_points.is_empty() {
+        println!(
+            "Warning: '{}' has {} point(s) with invalid coordinates (e.g. {}), use the 'validate' command for details",
+            file.as_ref().display(),
+            invalid_points.len(),
+            invalid_points[0]
+        );
+    }
+
+    Ok(gpx)
 }
 
-/// Construct of path of the output file for an operation on an input file or directory.
-fn get_output_file_path(path: &impl AsRef<Path>, action: Action) -> PathBuf {
-    let path = path.as_ref();
+/// Read GPX data from a file, without printing anything (unlike `load_gpx()`).
+/// This is for commands whose output must not be polluted, like the ones that print JSON.
+///
+/// A file starting with `http://` or `https://` is downloaded, with the `http` feature.
+/// With `read_options.strict`, it fails if the structure of the data is broken (see `ReadOptions`).
+pub fn read_gpx_file(
+    file: &impl AsRef<Path>,
+    read_options: &ReadOptions,
+) -> eyre::Result<gpx::Gpx> {
+    let gpx = match as_url(file.as_ref()) {
+        Some(url) => read_gpx(download(url)?.as_slice())?,
+        None => read_gpx(BufReader::new(File::open(file)?))?,
+    };
 
-    if path.is_dir() {
-        path.join(action.to_string()).with_extension("gpx")
-    } else {
-        let ext = path.extension().expect("Path should have an extension");
-        let stem = path
-            .file_stem()
-            .expect("Path should have a stem")
-            .to_str()
-            .expect("Should be able to convert to string")
-            .to_owned()
-            + "-"
-            + &action.to_string();
+    check_structure(&gpx, file, read_options)?;
+    Ok(gpx)
+}
 
-        let base = path.parent().expect("Path should have a parent");
-        base.join(stem).with_extension(ext)
-    }
+/// Read GPX data from any source, for instance from memory or from an archive.
+/// This is what `read_gpx_file()` does after opening the file.
+pub fn read_gpx<R: BufRead>(reader: R) -> eyre::Result<gpx::Gpx> {
+    Ok(gpx::read(reader)?)
 }
 
-fn print_field<T: Debug>(key: &str, value: T) {
-    println!("{key} = {value:?}");
+/// Get the URL of a file given as `http://...` or `https://...`.
+fn as_url(file: &Path) -> Option<&str> {
+    file.to_str()
+        .filter(|file| file.starts_with("http://") || file.starts_with("https://"))
 }
 
-fn print_option_field_debug<T: Debug>(key: &str, option: &Option<T>) {
-    if let Some(value) = option {
-        println!("{key} = {value:?}");
-    }
+/// Remove the query and the fragment of a URL, which are not part of the name of the file.
+fn strip_url_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
 }
 
-fn print_option_field<T: Display>(key: &str, option: &Option<T>) {
-    if let Some(value) = option {
-        println!("{key} = {value}");
+/// Download the content of a file given by a URL.
+fn download(url: &str) -> eyre::Result<Vec<u8>> {
+    #[cfg(feature = "http")]
+    {
+        http::download(url)
+    }
+
+    #[cfg(not(feature = "http"))]
+    {
+        Err(eyre!(
+            "Cannot read '{url}' since URLs are supported only with the 'http' feature"
+        ))
     }
 }
 
-fn print_vec_field<T: Debug>(key: &str, value: &Vec<T>) {
-    if !value.is_empty() {
-        println!("{key} = {value:?}");
+/// Warn if a file has extensions that are lost when it is read, such as the heart rate or the cadence of the points.
+/// Only the extensions of the tracks are kept (and only by some commands).
+fn warn_if_extensions_are_dropped(
+    file: &impl AsRef<Path>,
+    reader: impl std::io::Read,
+) -> eyre::Result<()> {
+    if let Some(warning) = describe_dropped_extensions(file, reader)? {
+        println!("{warning}");
     }
+
+    Ok(())
 }
 
-//----------------------------------------------------------------------------------------
-// Functions for the commands
+/// Get the warning of `warn_if_extensions_are_dropped()`, if the file has extensions that are lost.
+fn describe_dropped_extensions(
+    file: &impl AsRef<Path>,
+    reader: impl std::io::Read,
+) -> eyre::Result<Option<String>> {
+    let counts = extensions::count_dropped_extensions(reader)?;
+    if counts.is_empty() {
+        return Ok(None);
+    }
 
-pub fn info(files: &[impl AsRef<Path>]) -> eyre::Result<()> {
-    check_files(files)?;
+    let elements = counts
+        .iter()
+        .map(|(parent, count)| format!("{count} <{parent}>"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(Some(format!(
+        "Warning: the extensions of {elements} in '{}' are not supported and will be lost (e.g. heart rate, cadence, power)",
+        file.as_ref().display()
+    )))
+}
 
-    for file in files {
-        let path = file.as_ref();
+/// The distance (in degrees) from `0, 0` under which a point is considered to be at "Null Island",
+/// about 10 centimeters at the equator.
+const NULL_ISLAND_EPSILON_DEG: f64 = 1e-6;
 
-        println!("******************************************");
-        println!("Info about {}", path.display());
+/// Check whether a point is at `0, 0` ("Null Island", in the Atlantic Ocean), or very close to it:
+/// broken recorders write these coordinates when they have no fix.
+fn is_at_null_island(point: &gpx::Waypoint) -> bool {
+    let (lat, lon) = (point.point().y(), point.point().x());
+    lat.abs() < NULL_ISLAND_EPSILON_DEG && lon.abs() < NULL_ISLAND_EPSILON_DEG
+}
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let gpx = gpx::read(reader)?;
+/// Check that the coordinates of a point are numbers within their ranges, and not at Null Island
+/// (see `is_at_null_island()`).
+///
+/// The GPX parser already rejects NaN and out-of-range values, but data can come from other sources (e.g. GeoJSON).
+fn has_valid_coordinates(point: &gpx::Waypoint) -> bool {
+    let (lat, lon) = (point.point().y(), point.point().x());
+    (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) && !is_at_null_island(point)
+}
 
-        // Version
-        println!("GPX version = {}", gpx.version);
-        print_option_field("Creator", &gpx.creator);
+/// Find the points whose coordinates are invalid (see `has_valid_coordinates()`).
+/// They are described by their location in the data, e.g. "track #0 / segment #1 / point #42 (NaN, 5.1)".
+fn find_invalid_points(gpx: &gpx::Gpx) -> Vec<String> {
+    let describe = |location: String, point: &gpx::Waypoint| {
+        format!("{location} ({}, {})", point.point().y(), point.point().x())
+    };
 
-        println!("-- Metadata ------------------------------");
+    let mut invalid_points = Vec::new();
 
-        if let Some(metadata) = gpx.metadata {
-            print_option_field("Name", &metadata.name);
-            print_option_field("Description", &metadata.description);
-            print_option_field_debug("Author", &metadata.author);
-            print_vec_field("Links", &metadata.links);
-            print_option_field_debug("Time", &metadata.time);
-            print_option_field("Keywords", &metadata.keywords);
-            print_option_field_debug("Copyright", &metadata.copyright);
-            print_option_field_debug("Bounds", &metadata.bounds);
+    for (t, track) in gpx.tracks.iter().enumerate() {
+        for (s, segment) in track.segments.iter().enumerate() {
+            for (p, point) in segment.points.iter().enumerate() {
+                if !has_valid_coordinates(point) {
+                    let location = format!("track #{t} / segment #{s} / point #{p}");
+                    invalid_points.push(describe(location, point));
+                }
+            }
         }
+    }
 
-        println!("-- Waypoints -----------------------------");
-        for (i, waypoint) in gpx.waypoints.iter().enumerate() {
-            println!("-- Waypoints #{i} --------------------------");
-            print_option_field("Name", &waypoint.name);
-            print_field("Point", &waypoint.point());
-            print_option_field("elevation", &waypoint.elevation);
-            print_option_field("comment", &waypoint.comment);
-            print_option_field("description", &waypoint.description);
-            print_option_field("source", &waypoint.source);
+    for (r, route) in gpx.routes.iter().enumerate() {
+        for (p, point) in route.points.iter().enumerate() {
+            if !has_valid_coordinates(point) {
+                invalid_points.push(describe(format!("route #{r} / point #{p}"), point));
+            }
         }
+    }
 
-        println!("-- Tracks --------------------------------");
-        for (i, track) in gpx.tracks.iter().enumerate() {
-            println!("---- Track #{i}  ----------------------------");
-            print_option_field("Name", &track.name);
-            print_option_field("Comment", &track.comment);
-            print_option_field("Description", &track.description);
-            print_option_field("Source", &track.source);
-            print_vec_field("Links", &track.links);
-            print_option_field("Type", &track.type_);
-            print_option_field("Number", &track.number);
-
-            for (i, segment) in track.segments.iter().enumerate() {
-                println!("Segment #{i} = {} points", segment.points.len())
-            }
+    for (w, point) in gpx.waypoints.iter().enumerate() {
+        if !has_valid_coordinates(point) {
+            invalid_points.push(describe(format!("waypoint #{w}"), point));
         }
+    }
 
-        let track_count = gpx.tracks.len();
-        let segment_count = gpx
-            .tracks
-            .iter()
-            .map(|track| track.segments.len())
-            .sum::<usize>();
-        let point_count = gpx
-            .tracks
-            .iter()
-            .flat_map(|track| track.segments.clone())
-            .map(|segment| segment.points.len())
-            .sum::<usize>();
+    invalid_points
+}
 
-        println!(
-            "Total: {} tracks / {} segments / {} points",
-            track_count, segment_count, point_count
-        );
+/// Get the path of the temporary file used to write `file`.
+/// It lives in the same directory so that it can be renamed to `file` atomically.
+fn get_temporary_file_path(file: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(file.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    file.with_file_name(name)
+}
 
-        println!("-- Routes --------------------------------");
-        print_vec_field("Routes", &gpx.routes);
+/// Write a file through `write`.
+///
+/// The data is first written to a temporary file, which is then renamed to `file`.
+/// Hence, if anything goes wrong while writing, a previous version of `file` is left untouched.
+fn write_file_atomically(
+    file: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    let temporary_file = get_temporary_file_path(file);
 
-        println!("******************************************");
+    let result = (|| -> eyre::Result<()> {
+        let f = File::create(&temporary_file)?;
+        let mut writer = BufWriter::new(f);
+        write(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+
+        // On Windows, `rename` also replaces the destination if it already exists.
+        std::fs::rename(&temporary_file, file)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        // The temporary file may not exist, depending on where the error occurred.
+        let _ = std::fs::remove_file(&temporary_file);
     }
 
-    Ok(())
+    result
 }
 
-pub fn invert(files: &[impl AsRef<Path>]) -> eyre::Result<()> {
-    check_files(files)?;
+/// Options for writing GPX files, shared by all the commands.
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    /// Read the file back after writing it, and check that it has the same tracks, segments and points.
+    pub verify: bool,
+    /// Start the files with a UTF-8 byte order mark, for the tools that require it.
+    /// The files always start with an XML declaration telling their encoding (after the mark, if any).
+    pub with_bom: bool,
+    /// Do not indent the XML, to make the files smaller.
+    pub compact: bool,
+    /// Remove the elevations of all the points before writing.
+    pub strip_elevation: bool,
+    /// Round the elevations of all the points to this number of decimals before writing
+    /// (see `transform::RoundElevation`).
+    pub elevation_precision: Option<u8>,
+    /// Remove the times of all the points before writing.
+    pub strip_time: bool,
+    /// Remove all the waypoints before writing, to get files with only tracks and routes.
+    pub strip_waypoints: bool,
+    /// Do not write anything, only tell what would be written.
+    pub dry_run: bool,
+    /// Tell the size of each GPX file, by writing it in memory; combine with `dry_run` to only tell the size,
+    /// e.g. to tune the parameters of a decimation against the size limit of a website.
+    pub estimate_size: bool,
+    /// Remove the points at Null Island before writing (see `transform::DropNullIsland`).
+    pub drop_null_island: bool,
+    /// Remove the segments with fewer points before writing (see `transform::PruneShortSegments`).
+    pub min_segment_points: Option<usize>,
+    /// Write the speed of each track point (see `transform::AddSpeed`), in a Garmin `TrackPointExtension`.
+    pub add_speed: bool,
+    /// The suffix added to the names of the output files, instead of the one derived from the action
+    /// (e.g. `inverted`); `{action}` is replaced by the derived suffix.
+    pub output_suffix: Option<String>,
+    /// Add this time to the names of the output files, after the suffix, like `-20240601-080000`,
+    /// so that running a command again does not overwrite the previous outputs.
+    pub output_timestamp: Option<OffsetDateTime>,
+    /// Write pure ASCII files, for legacy devices: the non-ASCII characters of all the texts
+    /// (names, descriptions, comments, sources, links...) are written as numeric character references.
+    /// There is no byte order mark then, even with `with_bom`, since it is not ASCII.
+    pub ascii: bool,
+    /// Give the root element of the files the location of the GPX schema (`xsi:schemaLocation`),
+    /// for the importers that validate the files against it.
+    pub add_schema_location: bool,
+}
 
-    let output_files = files
-        .iter()
-        .map(|f| get_output_file_path(f, Action::Invert))
-        .collect::<Vec<_>>();
+/// The UTF-8 encoding of the byte order mark (U+FEFF).
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
 
-    for (in_file, out_file) in zip(files, output_files) {
-        let mut gpx = load_gpx(&in_file)?;
+/// Save GPX data to a file, as `write_gpx_with_options()` writes it.
+pub fn save_gpx(
+    gpx: &gpx::Gpx,
+    file: &impl AsRef<Path>,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    save_gpx_file(gpx, file.as_ref(), options, |gpx, writer| {
+        write_gpx_with_track_extensions(gpx, &[], writer, options)
+    })
+}
 
-        gpx.tracks.reverse();
+/// Same as `save_gpx()`, also writing the extensions of the tracks (in the order of `gpx.tracks`).
+fn save_gpx_with_track_extensions(
+    gpx: &gpx::Gpx,
+    tracks_extensions: &[Option<TrackExtensions>],
+    file: &impl AsRef<Path>,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    save_gpx_file(gpx, file.as_ref(), options, |gpx, writer| {
+        write_gpx_with_track_extensions(gpx, tracks_extensions, writer, options)
+    })
+}
 
-        for track in &mut gpx.tracks {
-            track.name = track
-                .name
-                .clone()
-                .map(|name| format!("{name} ({})", Action::Invert));
-            track.segments.reverse();
+/// Save GPX data to a file with `write`, which is given the data prepared for writing (see `prepare_for_writing()`).
+/// The data is prepared once, for both the estimation of the size and the file.
+fn save_gpx_file(
+    gpx: &gpx::Gpx,
+    file: &Path,
+    options: &WriteOptions,
+    write: impl Fn(&gpx::Gpx, &mut dyn Write) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    assert!(has_extension(file, "gpx"));
 
-            for segment in &mut track.segments {
-                segment.points.reverse();
-            }
-        }
+    if options.dry_run && !options.estimate_size {
+        println!("Dry run: not saving GPX to '{}'", file.display());
+        return Ok(());
+    }
 
-        gpx.creator = Some(get_creator());
+    let gpx = prepare_for_writing(gpx, options)?;
+
+    if options.estimate_size {
+        let mut buffer = Vec::new();
+        write(&gpx, &mut buffer)?;
+        println!(
+            "Estimated size of '{}': {} bytes",
+            file.display(),
+            buffer.len()
+        );
+    }
 
-        save_gpx(&gpx, &out_file)?;
+    if options.dry_run {
+        println!("Dry run: not saving GPX to '{}'", file.display());
+        return Ok(());
     }
 
-    Ok(())
-}
+    println!("Saving GPX to '{}'...", file.display());
 
-pub fn invert_all(directory: &impl AsRef<Path>) -> eyre::Result<()> {
-    check_directory(directory)?;
-    let files = list_gpx_files(directory)?;
+    write_file_atomically(file, |writer| write(&gpx, writer))
+        .map_err(|err| eyre!("Cannot save GPX to '{}': {err}", file.display()))?;
 
-    if files.is_empty() {
-        println!("No GPX files found in '{}'", directory.as_ref().display());
-        return Ok(());
+    if options.verify {
+        verify_gpx_file(&gpx, file)?;
     }
 
-    invert(&files)
+    Ok(())
 }
 
-pub fn merge(files: &[impl AsRef<Path>], output_file: &impl AsRef<Path>) -> eyre::Result<()> {
-    check_files(files)?;
+/// Apply the options that change the data that is written (stripping and pruning).
+/// A copy is only made when something must be stripped or pruned.
+/// Preparing data again changes nothing (and prints nothing), so prepared data can be given to `write_gpx_with_options()`.
+fn prepare_for_writing<'a>(
+    gpx: &'a gpx::Gpx,
+    options: &WriteOptions,
+) -> eyre::Result<Cow<'a, gpx::Gpx>> {
+    if !options.strip_elevation
+        && options.elevation_precision.is_none()
+        && !options.strip_time
+        && !options.strip_waypoints
+        && !options.drop_null_island
+        && options.min_segment_points.is_none()
+        && !options.add_speed
+    {
+        return Ok(Cow::Borrowed(gpx));
+    }
 
-    println!("Merging {} files...", files.len());
+    let mut copy = gpx.clone();
+    transform::Strip {
+        elevation: options.strip_elevation,
+        time: options.strip_time,
+    }
+    .apply(&mut copy)?;
 
-    let gpxs = files.iter().map(load_gpx).collect::<Result<Vec<_>, _>>()?;
+    if options.strip_waypoints {
+        copy.waypoints.clear();
+    }
 
-    let segments = gpxs
-        .iter()
-        .flat_map(|elem| elem.tracks.clone())
-        .flat_map(|track| track.segments)
-        .collect::<Vec<_>>();
+    if let Some(digits) = options.elevation_precision {
+        transform::RoundElevation { digits }.apply(&mut copy)?;
+    }
 
-    let track = gpx::Track {
-        segments,
-        ..Default::default()
-    };
+    // Before pruning, so that the segments left with too few points are pruned.
+    if options.drop_null_island {
+        let count = |gpx: &gpx::Gpx| {
+            let route_points = gpx.routes.iter().map(|route| route.points.len());
+            count_points(gpx) + route_points.sum::<usize>() + gpx.waypoints.len()
+        };
+        transform::DropNullIsland.apply(&mut copy)?;
+        let dropped = count(gpx) - count(&copy);
+        if dropped > 0 {
+            println!("Dropped {dropped} point(s) at Null Island (0, 0)");
+        }
+    }
 
-    let gpx = gpx::Gpx {
-        creator: Some(get_creator()),
-        version: gpx::GpxVersion::Gpx11,
-        tracks: vec![track],
-        ..Default::default()
-    };
+    if let Some(min_points) = options.min_segment_points {
+        transform::PruneShortSegments { min_points }.apply(&mut copy)?;
+        let pruned = count_elements(gpx).segments - count_elements(&copy).segments;
+        if pruned > 0 {
+            println!("Pruned {pruned} segment(s) with fewer than {min_points} points");
+        }
+    }
 
-    save_gpx(&gpx, output_file)?;
+    // After stripping, so that the points without times get no speed.
+    if options.add_speed {
+        transform::AddSpeed.apply(&mut copy)?;
+    }
 
-    Ok(())
+    Ok(Cow::Owned(copy))
 }
 
-pub fn merge_all(directory: &impl AsRef<Path>) -> eyre::Result<()> {
-    check_directory(directory)?;
-    let files = list_gpx_files(directory)?;
+/// Write GPX data to any sink, for instance to a `Vec<u8>` to inspect it without touching the disk,
+/// with the default options (see `write_gpx_with_options()`).
+pub fn write_gpx<W: Write>(gpx: &gpx::Gpx, writer: W) -> eyre::Result<()> {
+    write_gpx_with_options(gpx, writer, &WriteOptions::default())
+}
 
-    if files.is_empty() {
-        println!("No GPX files found in '{}'", directory.as_ref().display());
-        return Ok(());
+/// Same as `write_gpx()`, with the options of the files written (stripping, compact XML...).
+/// This is what `save_gpx()` does after opening the file (except for the verification).
+pub fn write_gpx_with_options<W: Write>(
+    gpx: &gpx::Gpx,
+    writer: W,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    let gpx = prepare_for_writing(gpx, options)?;
+    write_gpx_with_track_extensions(&gpx, &[], writer, options)
+}
+
+/// Same as `write_gpx()`, with the extensions of the tracks, and without preparing the data.
+fn write_gpx_with_track_extensions(
+    gpx: &gpx::Gpx,
+    tracks_extensions: &[Option<TrackExtensions>],
+    mut writer: impl Write,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    if options.with_bom && !options.ascii {
+        writer.write_all(UTF8_BOM)?;
     }
 
-    let output_file = get_output_file_path(directory, Action::Merge);
-    merge(&files, &output_file)
+    if options.ascii {
+        let mut buffer = Vec::new();
+        write_gpx_xml(gpx, tracks_extensions, &mut buffer, options)?;
+        writer.write_all(escape_non_ascii(&String::from_utf8(buffer)?).as_bytes())?;
+        Ok(())
+    } else {
+        write_gpx_xml(gpx, tracks_extensions, writer, options)
+    }
 }
 
-pub fn decimate(files: &[impl AsRef<Path>], factor_m: u16) -> eyre::Result<()> {
-    check_files(files)?;
+/// Replace the non-ASCII characters of an XML document with numeric character references (e.g. `&#233;` for `é`).
+/// The document is unchanged for XML parsers, but it is pure ASCII, for the legacy devices that require it.
+fn escape_non_ascii(xml: &str) -> String {
+    let mut escaped = String::with_capacity(xml.len());
+    for c in xml.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            escaped.push_str(&format!("&#{};", u32::from(c)));
+        }
+    }
+    escaped
+}
 
-    let output_files = files
-        .iter()
-        .map(|f| get_output_file_path(f, Action::Decimate(factor_m)))
-        .collect::<Vec<_>>();
+/// Write the XML of GPX data, with the extensions of the tracks, the speeds of the points (if needed)
+/// and the copyright of the metadata.
+fn write_gpx_xml(
+    gpx: &gpx::Gpx,
+    tracks_extensions: &[Option<TrackExtensions>],
+    writer: impl Write,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    let copyright = gpx
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.copyright.as_ref());
 
-    for (in_file, out_file) in zip(files, output_files) {
-        let mut gpx = load_gpx(&in_file)?;
+    if tracks_extensions.iter().all(Option::is_none)
+        && !options.add_speed
+        && copyright.is_none()
+        && !options.add_schema_location
+    {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(!options.compact)
+            .create_writer(writer);
+        gpx::write_with_event_writer(gpx, &mut writer)?;
+    } else {
+        let mut buffer = Vec::new();
+        gpx::write(gpx, &mut buffer)?;
 
-        for track in &mut gpx.tracks {
-            track.name = track
-                .name
-                .clone()
-                .map(|name| format!("{name} ({})", Action::Decimate(factor_m)));
+        if options.add_speed {
+            let speeds = gpx
+                .tracks
+                .iter()
+                .flat_map(|track| &track.segments)
+                .flat_map(|segment| &segment.points)
+                .map(|point| point.speed)
+                .collect::<Vec<_>>();
 
-            for segment in &mut track.segments {
-                segment.points = segment
+            let mut with_speeds = Vec::new();
+            extensions::insert_point_speeds(buffer.as_slice(), &mut with_speeds, &speeds)?;
+            buffer = with_speeds;
+        }
+
+        if let Some(copyright) = copyright {
+            let mut with_copyright = Vec::new();
+            extensions::insert_metadata_copyright(
+                buffer.as_slice(),
+                &mut with_copyright,
+                copyright,
+            )?;
+            buffer = with_copyright;
+        }
+
+        if options.add_schema_location {
+            let mut with_schema_location = Vec::new();
+            extensions::insert_schema_location(buffer.as_slice(), &mut with_schema_location)?;
+            buffer = with_schema_location;
+        }
+
+        extensions::insert_track_extensions(
+            buffer.as_slice(),
+            writer,
+            tracks_extensions,
+            !options.compact,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Describe the shape of GPX data: the numbers of points of the segments of the tracks,
+/// the numbers of points of the routes, and the number of waypoints.
+fn describe_shape(gpx: &gpx::Gpx) -> (Vec<Vec<usize>>, Vec<usize>, usize) {
+    let tracks = gpx
+        .tracks
+        .iter()
+        .map(|track| {
+            track
+                .segments
+                .iter()
+                .map(|segment| segment.points.len())
+                .collect()
+        })
+        .collect();
+    let routes = gpx.routes.iter().map(|route| route.points.len()).collect();
+
+    (tracks, routes, gpx.waypoints.len())
+}
+
+/// Check that a file has been written correctly, by reading it back and comparing it with the data that was written.
+fn verify_gpx_file(gpx: &gpx::Gpx, file: &Path) -> eyre::Result<()> {
+    println!("Verifying '{}'...", file.display());
+
+    let written = read_gpx_file(&file, &ReadOptions::default())
+        .map_err(|err| eyre!("Verification of '{}' failed: {err}", file.display()))?;
+
+    let (expected, actual) = (describe_shape(gpx), describe_shape(&written));
+    if expected != actual {
+        return Err(eyre!(
+            "Verification of '{}' failed: expected (tracks' segments' points, routes' points, waypoints) {expected:?}, got {actual:?}",
+            file.display()
+        ));
+    }
+
+    let get_bbox = |gpx: &gpx::Gpx| {
+        BoundingBox::from_points(
+            gpx.tracks
+                .iter()
+                .flat_map(|track| &track.segments)
+                .flat_map(|segment| &segment.points),
+        )
+    };
+
+    let (expected, actual) = (get_bbox(gpx), get_bbox(&written));
+    if expected != actual {
+        return Err(eyre!(
+            "Verification of '{}' failed: expected the bounding box {expected:?}, got {actual:?}",
+            file.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The formats in which the commands can write their results.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    Gpx,
+    Geojson,
+    Kml,
+    Csv,
+}
+
+impl OutputFormat {
+    /// Get the extension of the files of this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Gpx => "gpx",
+            OutputFormat::Geojson => "geojson",
+            OutputFormat::Kml => "kml",
+            OutputFormat::Csv => "csv",
+        }
+    }
+
+    /// Guess the format of a file from its extension.
+    pub fn from_path(file: &impl AsRef<Path>) -> Option<OutputFormat> {
+        let extension = file.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(OutputFormat::Geojson),
+            extension => extension.parse().ok(),
+        }
+    }
+}
+
+/// The order of the coordinates of the points, in the formats where it is not fixed.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum AxisOrder {
+    /// Longitude first, as in GeoJSON and KML.
+    LonLat,
+    /// Latitude first, as usually written by humans.
+    LatLon,
+}
+
+impl AxisOrder {
+    /// Put a latitude and a longitude (or anything related to them) in this order.
+    pub(crate) fn arrange<T>(&self, lat: T, lon: T) -> [T; 2] {
+        match self {
+            AxisOrder::LonLat => [lon, lat],
+            AxisOrder::LatLon => [lat, lon],
+        }
+    }
+}
+
+/// Save GPX data as GeoJSON (see `geojson::gpx_to_feature_collection()`).
+fn save_geojson(
+    gpx: &gpx::Gpx,
+    file: &Path,
+    trackpoints_only: bool,
+    order: AxisOrder,
+) -> eyre::Result<()> {
+    println!("Saving GeoJSON to '{}'...", file.display());
+
+    let document = geojson::gpx_to_feature_collection(gpx, trackpoints_only, order);
+    write_file_atomically(file, |writer| {
+        serde_json::to_writer(writer, &document)?;
+        Ok(())
+    })
+    .map_err(|err| eyre!("Cannot save GeoJSON to '{}': {err}", file.display()))
+}
+
+/// Save GPX data as CSV (see `csv::write_csv()`).
+fn save_csv(
+    gpx: &gpx::Gpx,
+    file: &Path,
+    trackpoints_only: bool,
+    order: AxisOrder,
+) -> eyre::Result<()> {
+    println!("Saving CSV to '{}'...", file.display());
+
+    write_file_atomically(file, |writer| {
+        csv::write_csv(gpx, writer, trackpoints_only, order)
+    })
+    .map_err(|err| eyre!("Cannot save CSV to '{}': {err}", file.display()))
+}
+
+/// Save the elevation profile of GPX data as plain text (see `profile::write_profile()`).
+fn save_profile(
+    gpx: &gpx::Gpx,
+    file: &Path,
+    distance_unit: DistanceUnit,
+    elevation_unit: ElevationUnit,
+) -> eyre::Result<()> {
+    println!("Saving profile to '{}'...", file.display());
+
+    write_file_atomically(file, |writer| {
+        profile::write_profile(gpx, writer, distance_unit, elevation_unit)
+    })
+    .map_err(|err| eyre!("Cannot save profile to '{}': {err}", file.display()))
+}
+
+/// Algorithm used to simplify the tracks (see `transform::SimplifyRdp` and `transform::SimplifyVisvalingam`).
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum SimplifyAlgorithm {
+    /// Ramer-Douglas-Peucker.
+    Rdp,
+    /// Visvalingam-Whyatt.
+    Visvalingam,
+}
+
+/// Metric used to color the lines of the tracks, in KML.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum ColorBy {
+    Speed,
+    Elevation,
+}
+
+/// Save GPX data as KML (see `kml::write_kml()`).
+fn save_kml(gpx: &gpx::Gpx, file: &Path, coloring: Option<&kml::Coloring>) -> eyre::Result<()> {
+    println!("Saving KML to '{}'...", file.display());
+
+    let name = gpx
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.name.as_deref());
+    write_file_atomically(file, |writer| kml::write_kml(gpx, writer, name, coloring))
+        .map_err(|err| eyre!("Cannot save KML to '{}': {err}", file.display()))
+}
+
+/// Save GPX data to a file in the given format.
+fn save_as(
+    gpx: &gpx::Gpx,
+    file: &Path,
+    format: OutputFormat,
+    options: &WriteOptions,
+) -> eyre::Result<()> {
+    if options.dry_run && format != OutputFormat::Gpx {
+        println!("Dry run: not saving {format} to '{}'", file.display());
+        return Ok(());
+    }
+
+    match format {
+        OutputFormat::Gpx => save_gpx(gpx, &file, options),
+        OutputFormat::Geojson => save_geojson(gpx, file, false, AxisOrder::LonLat),
+        OutputFormat::Kml => save_kml(gpx, file, None),
+        OutputFormat::Csv => save_csv(gpx, file, false, AxisOrder::LatLon),
+    }
+}
+
+/// Load the extensions of the tracks of a GPX file.
+fn load_track_extensions(file: &impl AsRef<Path>) -> eyre::Result<Vec<Option<TrackExtensions>>> {
+    if let Some(url) = as_url(file.as_ref()) {
+        return extensions::read_track_extensions(download(url)?.as_slice());
+    }
+
+    let reader = BufReader::new(File::open(file)?);
+    extensions::read_track_extensions(reader)
+}
+
+/// Get the value to use for the "creator" field for files we create.
+fn get_creator() -> String {
+    format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Clone, Copy, Display)]
+enum Action {
+    #[strum(serialize = "anonymized")]
+    Anonymize,
+    #[strum(serialize = "decimated-by-{0}")]
+    Decimate(u16),
+    #[strum(serialize = "extract")]
+    Extract,
+    #[strum(serialize = "from-geojson")]
+    FromGeojson,
+    #[strum(serialize = "gap-filled")]
+    GapFill,
+    #[strum(serialize = "inverted")]
+    Invert,
+    #[strum(serialize = "segments-inverted")]
+    InvertSegments,
+    #[strum(serialize = "longest")]
+    KeepLongest,
+    #[strum(serialize = "merged")]
+    Merge,
+    #[strum(serialize = "near-route")]
+    NearRoute,
+    #[strum(serialize = "transformed")]
+    Pipeline,
+    #[strum(serialize = "converted-to-track")]
+    RouteToTrack,
+    #[strum(serialize = "profile")]
+    ToProfile,
+    #[strum(serialize = "exploded")]
+    SegmentsToTracks,
+    #[strum(serialize = "simplified")]
+    Simplify,
+    #[strum(serialize = "converted-to-route")]
+    TrackToRoute,
+    #[strum(serialize = "csv")]
+    ToCsv,
+    #[strum(serialize = "geojson")]
+    ToGeojson,
+    #[strum(serialize = "kml")]
+    ToKml,
+    #[strum(serialize = "flattened-to-waypoints")]
+    ToWaypoints,
+}
+
+/// Construct of path of the output file for an operation on an input file or directory.
+fn get_output_file_path(
+    path: &impl AsRef<Path>,
+    action: Action,
+    options: &WriteOptions,
+) -> PathBuf {
+    let path = path.as_ref();
+    let mut suffix = match &options.output_suffix {
+        Some(suffix) => suffix.replace("{action}", &action.to_string()),
+        None => action.to_string(),
+    };
+    if let Some(time) = options.output_timestamp {
+        suffix.push_str(&format!(
+            "-{:04}{:02}{:02}-{:02}{:02}{:02}",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        ));
+    }
+
+    if path.is_dir() {
+        path.join(suffix).with_extension("gpx")
+    } else if let Some(url) = as_url(path) {
+        // In the current directory, since the output cannot be written to the server.
+        let name = Path::new(strip_url_query(url))
+            .file_name()
+            .unwrap_or_default();
+        add_suffix_to_file_stem(Path::new(name), &suffix)
+    } else {
+        add_suffix_to_file_stem(path, &suffix)
+    }
+}
+
+/// Construct the path of the file exporting a file to another format: `a.gpx` becomes `a.geojson`,
+/// or is named as by `get_output_file_path()` when the names of the outputs are customized.
+fn get_export_file_path(
+    path: &impl AsRef<Path>,
+    format: OutputFormat,
+    action: Action,
+    options: &WriteOptions,
+) -> PathBuf {
+    if options.output_suffix.is_some() || options.output_timestamp.is_some() {
+        get_output_file_path(path, action, options).with_extension(format.extension())
+    } else {
+        path.as_ref().with_extension(format.extension())
+    }
+}
+
+/// Add a suffix to the stem of a file name, separated by a dash: `a/b.gpx` becomes `a/b-suffix.gpx`.
+fn add_suffix_to_file_stem(path: &Path, suffix: &str) -> PathBuf {
+    // Not with strings, since file names may not be valid UTF-8.
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push("-");
+    name.push(suffix);
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+
+    path.with_file_name(name)
+}
+
+/// Call `f` on every point of the file: waypoints, route points and track points.
+fn for_each_point_mut(gpx: &mut gpx::Gpx, mut f: impl FnMut(&mut gpx::Waypoint)) {
+    gpx.waypoints.iter_mut().for_each(&mut f);
+
+    gpx.routes
+        .iter_mut()
+        .flat_map(|route| &mut route.points)
+        .for_each(&mut f);
+
+    gpx.tracks
+        .iter_mut()
+        .flat_map(|track| &mut track.segments)
+        .flat_map(|segment| &mut segment.points)
+        .for_each(&mut f);
+}
+
+/// Warn the user if `gpx` (loaded from `file`) has no tracks, since there is then nothing to `do` with it.
+/// Returns `true` in this case.
+fn warn_if_no_tracks(gpx: &gpx::Gpx, file: &impl AsRef<Path>, what: &str) -> bool {
+    if gpx.tracks.is_empty() {
+        println!(
+            "Warning: no tracks in '{}', nothing to {what}",
+            file.as_ref().display()
+        );
+        true
+    } else {
+        false
+    }
+}
+
+/// Convert a route into a track with a single segment, keeping its name, description, links...
+fn route_into_track(route: gpx::Route) -> gpx::Track {
+    gpx::Track {
+        segments: vec![gpx::TrackSegment {
+            points: route.points,
+        }],
+        name: route.name,
+        comment: route.comment,
+        description: route.description,
+        source: route.source,
+        links: route.links,
+        type_: route.type_,
+        number: route.number,
+    }
+}
+
+/// Convert a track into a route, keeping its name, description, links...
+/// The points of all its segments are put one after the other, since a route has no segments.
+fn track_into_route(track: gpx::Track) -> gpx::Route {
+    gpx::Route {
+        points: track
+            .segments
+            .into_iter()
+            .flat_map(|segment| segment.points)
+            .collect(),
+        name: track.name,
+        comment: track.comment,
+        description: track.description,
+        source: track.source,
+        links: track.links,
+        type_: track.type_,
+        number: track.number,
+    }
+}
+
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Wrap a longitude (or a difference of longitudes) to [-180, 180[, in degrees.
+fn wrap_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Compute the great-circle distance (in meters) between two points, using the haversine formula.
+///
+/// The difference of longitudes is wrapped, so that points on each side of the antimeridian
+/// (e.g. at longitudes 179 and -179) are close to each other.
+fn distance(a: &gpx::Waypoint, b: &gpx::Waypoint) -> f64 {
+    let (lat_a, lat_b) = (a.point().y().to_radians(), b.point().y().to_radians());
+    let delta_lon = wrap_longitude(b.point().x() - a.point().x()).to_radians();
+
+    let h = ((lat_b - lat_a) / 2.0).sin().powi(2)
+        + lat_a.cos() * lat_b.cos() * (delta_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// A rectangle in latitude and longitude, in degrees.
+///
+/// When `min_lon` is greater than `max_lon`, the box crosses the antimeridian:
+/// it goes east from `min_lon` to 180, and continues from -180 to `max_lon`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    /// Compute the smallest box containing points.
+    ///
+    /// The box crosses the antimeridian if it is narrower this way,
+    /// so that a track from longitude 179 to -179 gets a box 2 degrees wide (and not 358).
+    /// Returns `None` if there is no point.
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a gpx::Waypoint>) -> Option<Self> {
+        let mut lats = Vec::new();
+        let mut lons = Vec::new();
+        for point in points {
+            lats.push(point.point().y());
+            lons.push(wrap_longitude(point.point().x()));
+        }
+
+        if lons.is_empty() {
+            return None;
+        }
+
+        lons.sort_by(f64::total_cmp);
+
+        // The box covers all the longitudes, except the largest gap between two consecutive longitudes.
+        // By default, this gap is the one crossing the antimeridian, from the last longitude to the first one.
+        let (first, last) = (lons[0], lons[lons.len() - 1]);
+        let (mut min_lon, mut max_lon) = (first, last);
+        let mut largest_gap = first + 360.0 - last;
+        for pair in lons.windows(2) {
+            if pair[1] - pair[0] > largest_gap {
+                largest_gap = pair[1] - pair[0];
+                (min_lon, max_lon) = (pair[1], pair[0]);
+            }
+        }
+
+        Some(BoundingBox {
+            min_lat: lats.iter().copied().fold(f64::INFINITY, f64::min),
+            min_lon,
+            max_lat: lats.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            max_lon,
+        })
+    }
+
+    /// Tell whether this box crosses the antimeridian.
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_lon > self.max_lon
+    }
+
+    /// Tell whether a point is inside this box (or on its border).
+    pub fn contains(&self, point: &gpx::Waypoint) -> bool {
+        let (lat, mut lon) = (point.point().y(), point.point().x());
+        if !(-180.0..=180.0).contains(&lon) {
+            lon = wrap_longitude(lon);
+        }
+
+        let contains_lon = if self.crosses_antimeridian() {
+            lon >= self.min_lon || lon <= self.max_lon
+        } else {
+            (self.min_lon..=self.max_lon).contains(&lon)
+        };
+
+        (self.min_lat..=self.max_lat).contains(&lat) && contains_lon
+    }
+}
+
+/// Compute the center of lines of points (tracks segments, routes...), averaging the points on the sphere.
+///
+/// With `weighted`, each point has a weight proportional to the length of the line around it,
+/// so that densely recorded parts of a line do not attract the center.
+/// Returns `None` if there is no point, or if the points are evenly spread around the globe.
+fn compute_center<'a>(
+    lines: impl Iterator<Item = &'a [gpx::Waypoint]>,
+    weighted: bool,
+) -> Option<(f64, f64)> {
+    let mut sum = [0.0; 3];
+    let mut total_weight = 0.0;
+
+    for points in lines {
+        for (i, point) in points.iter().enumerate() {
+            let weight = if weighted {
+                let before = i
+                    .checked_sub(1)
+                    .map_or(0.0, |j| distance(&points[j], point));
+                let after = points.get(i + 1).map_or(0.0, |next| distance(point, next));
+                (before + after) / 2.0
+            } else {
+                1.0
+            };
+
+            let (lat, lon) = (
+                point.point().y().to_radians(),
+                point.point().x().to_radians(),
+            );
+            sum[0] += weight * lat.cos() * lon.cos();
+            sum[1] += weight * lat.cos() * lon.sin();
+            sum[2] += weight * lat.sin();
+            total_weight += weight;
+        }
+    }
+
+    if total_weight == 0.0 {
+        return None;
+    }
+
+    let [x, y, z] = sum.map(|coordinate| coordinate / total_weight);
+    if (x * x + y * y + z * z).sqrt() < 1e-9 {
+        return None;
+    }
+
+    Some((
+        z.atan2((x * x + y * y).sqrt()).to_degrees(),
+        y.atan2(x).to_degrees(),
+    ))
+}
+
+/// Below this horizontal distance (in meters) between two points, no grade is computed.
+/// Otherwise, GPS jitter between very close points leads to absurd grades.
+const MIN_DISTANCE_FOR_GRADE_M: f64 = 1.0;
+
+/// Elevation statistics of a segment.
+struct ElevationStats {
+    min: f64,
+    max: f64,
+    net: f64,
+    /// Grade (as a percentage) with the largest absolute value, if any could be computed.
+    max_grade: Option<f64>,
+}
+
+/// Compute the elevation statistics of a segment, or `None` if its points have no elevation.
+fn compute_elevation_stats(segment: &gpx::TrackSegment) -> Option<ElevationStats> {
+    let elevations = segment
+        .points
+        .iter()
+        .filter_map(|point| point.elevation)
+        .collect::<Vec<_>>();
+
+    let first = *elevations.first()?;
+    let last = *elevations.last()?;
+
+    let max_grade = segment
+        .points
+        .windows(2)
+        .filter_map(|pair| {
+            let delta = pair[1].elevation? - pair[0].elevation?;
+            let horizontal = distance(&pair[0], &pair[1]);
+            (horizontal >= MIN_DISTANCE_FOR_GRADE_M).then_some(100.0 * delta / horizontal)
+        })
+        .max_by(|a, b| a.abs().total_cmp(&b.abs()));
+
+    Some(ElevationStats {
+        min: elevations.iter().copied().fold(f64::INFINITY, f64::min),
+        max: elevations.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        net: last - first,
+        max_grade,
+    })
+}
+
+fn print_field<T: Debug>(key: &str, value: T) {
+    println!("{key} = {value:?}");
+}
+
+fn print_option_field_debug<T: Debug>(key: &str, option: &Option<T>) {
+    if let Some(value) = option {
+        println!("{key} = {value:?}");
+    }
+}
+
+fn print_option_field<T: Display>(key: &str, option: &Option<T>) {
+    if let Some(value) = option {
+        println!("{key} = {value}");
+    }
+}
+
+fn print_vec_field<T: Debug>(key: &str, value: &[T]) {
+    if !value.is_empty() {
+        println!("{key} = {value:?}");
+    }
+}
+
+/// Tell how a transformation changed GPX data, for a dry run.
+/// The changes of the points are distinguished from the other changes (names, metadata...),
+/// and a transformation that changes nothing (e.g. decimating by 1) is reported explicitly.
+fn preview_changes(
+    in_file: &impl AsRef<Path>,
+    out_file: &impl AsRef<Path>,
+    original: &gpx::Gpx,
+    transformed: &gpx::Gpx,
+) {
+    // Segments have nothing but points.
+    let same_points = original
+        .tracks
+        .iter()
+        .map(|track| &track.segments)
+        .eq(transformed.tracks.iter().map(|track| &track.segments))
+        && original
+            .routes
+            .iter()
+            .map(|route| &route.points)
+            .eq(transformed.routes.iter().map(|route| &route.points))
+        && original.waypoints == transformed.waypoints;
+
+    // The creator is ignored, since every transformation sets it.
+    let change = if !same_points {
+        let (before, after) = (
+            count_elements(original).points,
+            count_elements(transformed).points,
+        );
+        format!("{before} -> {after} track points, would change")
+    } else if original.metadata != transformed.metadata
+        || original.tracks != transformed.tracks
+        || original.routes != transformed.routes
+    {
+        "the points would not change, only the names or the metadata".to_owned()
+    } else {
+        "nothing would change, the transformation is a no-op".to_owned()
+    };
+
+    println!(
+        "'{}' -> '{}': {change}",
+        in_file.as_ref().display(),
+        out_file.as_ref().display()
+    );
+}
+
+/// How to print JSON documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum JsonFormat {
+    /// On a single line, to pipe it to other tools.
+    #[default]
+    Compact,
+    /// Indented, for humans.
+    Pretty,
+}
+
+fn print_json(document: &serde_json::Value, format: JsonFormat) -> eyre::Result<()> {
+    match format {
+        JsonFormat::Compact => println!("{document}"),
+        JsonFormat::Pretty => println!("{}", serde_json::to_string_pretty(document)?),
+    }
+    Ok(())
+}
+
+/// Describe a file as JSON, with the same information as the human-readable output of `info`.
+fn info_to_json(
+    path: &Path,
+    gpx: &gpx::Gpx,
+    tracks_extensions: &[Option<TrackExtensions>],
+) -> eyre::Result<serde_json::Value> {
+    let metadata = match &gpx.metadata {
+        Some(metadata) => serde_json::json!({
+            "name": metadata.name,
+            "description": metadata.description,
+            "time": metadata.time.map(|time| time.format()).transpose()?,
+            "keywords": metadata.keywords,
+        }),
+        None => serde_json::Value::Null,
+    };
+
+    let tracks = gpx
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(i, track)| {
+            let segments = track
+                .segments
+                .iter()
+                .map(|segment| {
+                    let elevation = compute_elevation_stats(segment).map(|stats| {
+                        serde_json::json!({
+                            "min": stats.min,
+                            "max": stats.max,
+                            "net": stats.net,
+                            "max_grade": stats.max_grade,
+                        })
+                    });
+                    serde_json::json!({
+                        "points": segment.points.len(),
+                        "elevation": elevation,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::json!({
+                "name": track.name,
+                "type": track.type_,
+                "color": tracks_extensions
+                    .get(i)
+                    .and_then(Option::as_ref)
+                    .and_then(TrackExtensions::display_color),
+                "segments": segments,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let counts = count_elements(gpx);
+
+    Ok(serde_json::json!({
+        "file": path.to_string_lossy(),
+        "version": gpx.version.to_string(),
+        "creator": gpx.creator,
+        "metadata": metadata,
+        "waypoints": gpx.waypoints.len(),
+        "tracks": tracks,
+        "routes": gpx.routes.len(),
+        "total": {
+            "tracks": counts.tracks,
+            "segments": counts.segments,
+            "points": counts.points,
+        },
+    }))
+}
+
+/// Tell how many elements were not printed, when there are more than `limit`.
+fn print_more_line(count: usize, limit: usize) {
+    if count > limit {
+        println!("... and {} more", count - limit);
+    }
+}
+
+//----------------------------------------------------------------------------------------
+// Functions for the commands
+
+/// Describe each file as JSON, in a JSON array in the order of `files`.
+/// Nothing is printed until all the files are described, so that a failure leaves no partial document.
+fn describe_files_as_json(
+    files: &[impl AsRef<Path>],
+    describe: impl Fn(&Path) -> eyre::Result<serde_json::Value>,
+) -> eyre::Result<serde_json::Value> {
+    files
+        .iter()
+        .map(|file| describe(file.as_ref()))
+        .collect::<eyre::Result<Vec<_>>>()
+        .map(serde_json::Value::Array)
+}
+
+/// Print information about files.
+/// With `json`, a single JSON array describing all the files (in the order of `files`) is printed
+/// once they are all read, in this format (see `describe_files_as_json()`).
+/// Otherwise, at most `limit` waypoints, routes and segments per track are detailed (the totals are still complete).
+pub fn info(
+    files: &[impl AsRef<Path>],
+    json: Option<JsonFormat>,
+    limit: Option<usize>,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    if let Some(format) = json {
+        let document = describe_files_as_json(files, |path| {
+            let gpx = read_gpx_file(&path, read_options)?;
+            let tracks_extensions = load_track_extensions(&path)?;
+            info_to_json(path, &gpx, &tracks_extensions)
+        })?;
+        return print_json(&document, format);
+    }
+
+    let limit = limit.unwrap_or(usize::MAX);
+
+    for file in files {
+        let path = file.as_ref();
+
+        println!("******************************************");
+        println!("Info about {}", path.display());
+
+        let gpx = read_gpx_file(&path, read_options)?;
+        let tracks_extensions = load_track_extensions(&path)?;
+
+        // Version
+        println!("GPX version = {}", gpx.version);
+        print_option_field("Creator", &gpx.creator);
+
+        println!("-- Metadata ------------------------------");
+
+        if let Some(metadata) = &gpx.metadata {
+            print_option_field("Name", &metadata.name);
+            print_option_field("Description", &metadata.description);
+            print_option_field_debug("Author", &metadata.author);
+            print_vec_field("Links", &metadata.links);
+            print_option_field_debug("Time", &metadata.time);
+            print_option_field("Keywords", &metadata.keywords);
+            print_option_field_debug("Copyright", &metadata.copyright);
+            print_option_field_debug("Bounds", &metadata.bounds);
+        }
+
+        println!("-- Waypoints -----------------------------");
+        for (i, waypoint) in gpx.waypoints.iter().enumerate().take(limit) {
+            println!("-- Waypoints #{i} --------------------------");
+            print_option_field("Name", &waypoint.name);
+            print_field("Point", waypoint.point());
+            print_option_field("elevation", &waypoint.elevation);
+            print_option_field("comment", &waypoint.comment);
+            print_option_field("description", &waypoint.description);
+            print_option_field("source", &waypoint.source);
+        }
+        print_more_line(gpx.waypoints.len(), limit);
+
+        println!("-- Tracks --------------------------------");
+        for (i, track) in gpx.tracks.iter().enumerate() {
+            println!("---- Track #{i}  ----------------------------");
+            print_option_field("Name", &track.name);
+            print_option_field("Comment", &track.comment);
+            print_option_field("Description", &track.description);
+            print_option_field("Source", &track.source);
+            print_vec_field("Links", &track.links);
+            print_option_field("Type", &track.type_);
+            print_option_field("Number", &track.number);
+            print_option_field(
+                "Color",
+                &tracks_extensions
+                    .get(i)
+                    .and_then(Option::as_ref)
+                    .and_then(TrackExtensions::display_color),
+            );
+
+            for (i, segment) in track.segments.iter().enumerate().take(limit) {
+                println!("Segment #{i} = {} points", segment.points.len());
+
+                match compute_elevation_stats(segment) {
+                    Some(stats) => {
+                        let max_grade = stats
+                            .max_grade
+                            .map_or("n/a".to_owned(), |grade| format!("{grade:.1} %"));
+                        println!(
+                            "    Elevation: min = {:.1} m / max = {:.1} m / net = {:+.1} m / max grade = {max_grade}",
+                            stats.min, stats.max, stats.net
+                        );
+                    }
+                    None => println!("    Elevation: n/a"),
+                }
+            }
+            print_more_line(track.segments.len(), limit);
+        }
+
+        let counts = count_elements(&gpx);
+        println!(
+            "Total: {} tracks / {} segments / {} points",
+            counts.tracks, counts.segments, counts.points
+        );
+
+        if counts.tracks == 0 {
+            println!("This file has no tracks; most commands have nothing to do with it");
+        }
+
+        println!("-- Routes --------------------------------");
+        let routes = &gpx.routes[..gpx.routes.len().min(limit)];
+        print_vec_field("Routes", routes);
+        print_more_line(gpx.routes.len(), limit);
+
+        println!("******************************************");
+    }
+
+    Ok(())
+}
+
+/// A unit of distance, e.g. for the splits of the "stats" command.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum DistanceUnit {
+    Km,
+    Mi,
+}
+
+impl DistanceUnit {
+    /// The length of the unit, in meters.
+    fn length_m(&self) -> f64 {
+        match self {
+            DistanceUnit::Km => 1000.0,
+            DistanceUnit::Mi => 1609.344,
+        }
+    }
+
+    /// Convert a distance in this unit to meters.
+    pub fn to_meters(&self, distance: f64) -> f64 {
+        distance * self.length_m()
+    }
+
+    /// Convert a distance in meters to this unit.
+    pub fn from_meters(&self, distance_m: f64) -> f64 {
+        distance_m / self.length_m()
+    }
+}
+
+/// A unit of elevation.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum ElevationUnit {
+    M,
+    Ft,
+}
+
+impl ElevationUnit {
+    /// The length of the unit, in meters.
+    fn length_m(&self) -> f64 {
+        match self {
+            ElevationUnit::M => 1.0,
+            ElevationUnit::Ft => 0.3048,
+        }
+    }
+
+    /// Convert an elevation in this unit to meters.
+    pub fn to_meters(&self, elevation: f64) -> f64 {
+        elevation * self.length_m()
+    }
+
+    /// Convert an elevation in meters to this unit.
+    pub fn from_meters(&self, elevation_m: f64) -> f64 {
+        elevation_m / self.length_m()
+    }
+}
+
+/// A part of a track, of a given length (except for the last split, which is usually shorter).
+struct Split {
+    distance_m: f64,
+    /// The time to cover the split, if the points have times.
+    duration: Option<time::Duration>,
+}
+
+/// Interpolate the time at `ratio` of the way between two points, if both have a time.
+fn interpolate_time(a: &gpx::Waypoint, b: &gpx::Waypoint, ratio: f64) -> Option<OffsetDateTime> {
+    let (a, b) = (OffsetDateTime::from(a.time?), OffsetDateTime::from(b.time?));
+    Some(a + (b - a) * ratio)
+}
+
+/// Interpolate a point between two points, `ratio` being 0 at `a` and 1 at `b`.
+/// The elevation and the time are interpolated only if both points have them.
+pub(crate) fn interpolate_point(a: &gpx::Waypoint, b: &gpx::Waypoint, ratio: f64) -> gpx::Waypoint {
+    let lat = a.point().y() + (b.point().y() - a.point().y()) * ratio;
+    // Through the antimeridian if it is the shortest way.
+    let lon = wrap_longitude(a.point().x() + wrap_longitude(b.point().x() - a.point().x()) * ratio);
+
+    let mut point = gpx::Waypoint::new((lon, lat).into());
+    point.elevation = a
+        .elevation
+        .zip(b.elevation)
+        .map(|(a, b)| a + (b - a) * ratio);
+    point.time = interpolate_time(a, b, ratio).map(Into::into);
+    point
+}
+
+/// Cut the tracks into splits of `split_m` meters, in order, and compute the time of each split.
+///
+/// The points where the tracks cross the boundaries of the splits are interpolated between the recorded points.
+/// Gaps between segments do not count in the distance, but they count in the time.
+fn compute_splits(gpx: &gpx::Gpx, split_m: f64) -> Vec<Split> {
+    let mut splits = Vec::new();
+    let first_point = gpx
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .find_map(|segment| segment.points.first());
+
+    let Some(first_point) = first_point else {
+        return splits;
+    };
+
+    let mut split_distance = 0.0;
+    let mut split_start = first_point.time.map(OffsetDateTime::from);
+    let mut last_time = split_start;
+
+    for segment in gpx.tracks.iter().flat_map(|track| &track.segments) {
+        for pair in segment.points.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let pair_distance = distance(a, b);
+            let mut covered = 0.0;
+
+            while split_distance + pair_distance - covered >= split_m {
+                covered += split_m - split_distance;
+
+                let time = interpolate_time(a, b, covered / pair_distance);
+                splits.push(Split {
+                    distance_m: split_m,
+                    duration: time.zip(split_start).map(|(end, start)| end - start),
+                });
+
+                split_distance = 0.0;
+                split_start = time;
+            }
+
+            split_distance += pair_distance - covered;
+        }
+
+        if let Some(time) = segment.points.last().and_then(|point| point.time) {
+            last_time = Some(time.into());
+        }
+    }
+
+    if split_distance > 0.0 {
+        splits.push(Split {
+            distance_m: split_distance,
+            duration: last_time.zip(split_start).map(|(end, start)| end - start),
+        });
+    }
+
+    splits
+}
+
+/// Format a duration as hours, minutes and seconds (e.g. "1:02:03").
+fn format_duration(duration: time::Duration) -> String {
+    let seconds = duration.whole_seconds();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+/// Compute the total distance of the tracks (in meters), without the gaps between segments.
+fn compute_tracks_distance(gpx: &gpx::Gpx) -> f64 {
+    gpx.tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .map(compute_segment_distance)
+        .sum()
+}
+
+/// Compute the distance of a segment (in meters).
+fn compute_segment_distance(segment: &gpx::TrackSegment) -> f64 {
+    segment
+        .points
+        .windows(2)
+        .map(|pair| distance(&pair[0], &pair[1]))
+        .sum()
+}
+
+/// Compute the duration of the tracks, from the earliest to the latest time of their points.
+/// Returns `None` if no points have times.
+fn compute_duration(gpx: &gpx::Gpx) -> Option<time::Duration> {
+    let times = gpx
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points)
+        .filter_map(|point| point.time.map(OffsetDateTime::from));
+
+    times
+        .clone()
+        .min()
+        .zip(times.max())
+        .map(|(start, end)| end - start)
+}
+
+/// Compute the elevation gain of the tracks (in meters): the sum of the climbs between consecutive points
+/// of the segments, without smoothing. Returns `None` if no consecutive points both have an elevation.
+fn compute_elevation_gain(gpx: &gpx::Gpx) -> Option<f64> {
+    gpx.tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| segment.points.windows(2))
+        .filter_map(|pair| Some(pair[1].elevation? - pair[0].elevation?))
+        .fold(None, |gain, delta| {
+            Some(gain.unwrap_or(0.0) + delta.max(0.0))
+        })
+}
+
+/// Make a waypoint at the first track point, named "Summary", whose description tells the distance,
+/// the elevation gain and the duration of the tracks, for the map apps that show the descriptions of waypoints.
+/// Returns `None` if there are no track points.
+fn make_summary_waypoint(gpx: &gpx::Gpx) -> Option<gpx::Waypoint> {
+    let (first, _) = get_end_points(gpx, false)?;
+
+    let gain = compute_elevation_gain(gpx).map_or("n/a".to_owned(), |gain| format!("{gain:.0} m"));
+    let duration = compute_duration(gpx).map_or("n/a".to_owned(), format_duration);
+
+    let mut waypoint = gpx::Waypoint::new(first.point());
+    waypoint.name = Some("Summary".to_owned());
+    waypoint.description = Some(format!(
+        "Distance = {:.2} km / Elevation gain = {gain} / Duration = {duration}",
+        compute_tracks_distance(gpx) / 1000.0
+    ));
+    Some(waypoint)
+}
+
+/// Compute the moving time of the tracks: the time between consecutive points of the segments
+/// when the speed between them is more than `threshold_mps` (in meters per second).
+/// Returns `None` if no consecutive points both have a time.
+fn compute_moving_time(gpx: &gpx::Gpx, threshold_mps: f64) -> Option<time::Duration> {
+    let intervals = gpx
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| segment.points.windows(2))
+        .filter_map(|pair| {
+            let (a, b) = (pair[0].time?, pair[1].time?);
+            let duration = OffsetDateTime::from(b) - OffsetDateTime::from(a);
+            Some((duration, distance(&pair[0], &pair[1])))
+        })
+        .collect::<Vec<_>>();
+
+    if intervals.is_empty() {
+        return None;
+    }
+
+    let moving_time = intervals
+        .into_iter()
+        .filter(|(duration, distance_m)| {
+            duration.is_positive() && distance_m / duration.as_seconds_f64() > threshold_mps
+        })
+        .map(|(duration, _)| duration)
+        .sum();
+
+    Some(moving_time)
+}
+
+/// Print statistics about each file.
+/// The moving time only counts the time when the speed is more than `moving_threshold_mps` (in meters per second);
+/// the rest of the duration (including the gaps between segments) is the stopped time.
+///
+/// With `prefer_file_time`, the start is printed too, falling back to the time of the metadata of the file
+/// when the points have no times (the duration is still not available in this case).
+/// With `assume_speed_kmh`, the duration at this speed is estimated from the distance, e.g. to plan a ride.
+pub fn stats(
+    files: &[impl AsRef<Path>],
+    splits: Option<DistanceUnit>,
+    moving_threshold_mps: f64,
+    prefer_file_time: bool,
+    assume_speed_kmh: Option<f64>,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    if assume_speed_kmh.is_some_and(|speed_kmh| speed_kmh.is_nan() || speed_kmh <= 0.0) {
+        return Err(eyre!("The assumed speed should be positive"));
+    }
+
+    check_files(files)?;
+
+    for file in files {
+        let gpx = read_gpx_file(file, read_options)?;
+
+        println!("Stats for '{}':", file.as_ref().display());
+
+        let duration = compute_duration(&gpx);
+        let moving_time = compute_moving_time(&gpx, moving_threshold_mps);
+        let stopped_time = duration
+            .zip(moving_time)
+            .map(|(duration, moving_time)| duration - moving_time);
+
+        let format =
+            |duration: Option<time::Duration>| duration.map_or("n/a".to_owned(), format_duration);
+
+        let distance_m = compute_tracks_distance(&gpx);
+
+        println!("Points = {}", count_points(&gpx));
+        println!("Distance = {:.2} km", distance_m / 1000.0);
+
+        if prefer_file_time {
+            let point_time = gpx
+                .tracks
+                .iter()
+                .flat_map(|track| &track.segments)
+                .flat_map(|segment| &segment.points)
+                .find_map(|point| point.time);
+            let file_time = gpx.metadata.as_ref().and_then(|metadata| metadata.time);
+
+            let start = match (point_time, file_time) {
+                (Some(time), _) => time.format()?,
+                (None, Some(time)) => format!("{} (from the metadata of the file)", time.format()?),
+                (None, None) => "n/a".to_owned(),
+            };
+            println!("Start = {start}");
+        }
+
+        println!("Duration = {}", format(duration));
+        println!("Moving time = {}", format(moving_time));
+        println!("Stopped time = {}", format(stopped_time));
+
+        if let Some(speed_kmh) = assume_speed_kmh {
+            let estimate = time::Duration::seconds_f64(distance_m / (speed_kmh / 3.6));
+            println!(
+                "Estimated duration = {} (at {speed_kmh} km/h)",
+                format_duration(estimate)
+            );
+        }
+
+        if let Some(unit) = splits {
+            println!("Split | Distance ({unit}) | Time");
+
+            for (i, split) in compute_splits(&gpx, unit.length_m()).iter().enumerate() {
+                let time = split.duration.map_or("n/a".to_owned(), format_duration);
+                println!(
+                    "{:>5} | {:>13.2} | {time}",
+                    i + 1,
+                    unit.from_meters(split.distance_m)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the first (or last, with `from_end`) `count` points of the tracks of each file.
+fn print_points(
+    files: &[impl AsRef<Path>],
+    count: usize,
+    from_end: bool,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    for file in files {
+        let gpx = read_gpx_file(file, read_options)?;
+
+        // Like the Unix commands.
+        if files.len() > 1 {
+            println!("==> {} <==", file.as_ref().display());
+        }
+
+        let points = enumerate_track_points(&gpx).collect::<Vec<_>>();
+
+        let selected = if from_end {
+            &points[points.len().saturating_sub(count)..]
+        } else {
+            &points[..count.min(points.len())]
+        };
+
+        for (t, s, p, point) in selected {
+            println!("{}", format_track_point(*t, *s, *p, point)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Iterate over the points of the tracks, with the indices of their tracks, of their segments and their own.
+fn enumerate_track_points(
+    gpx: &gpx::Gpx,
+) -> impl Iterator<Item = (usize, usize, usize, &gpx::Waypoint)> {
+    gpx.tracks.iter().enumerate().flat_map(|(t, track)| {
+        track
+            .segments
+            .iter()
+            .enumerate()
+            .flat_map(move |(s, segment)| {
+                segment
                     .points
                     .iter()
                     .enumerate()
-                    .filter(|(i, _)| i % factor_m as usize == 0 || *i == segment.points.len() - 1)
-                    .map(|(_, element)| element.clone())
+                    .map(move |(p, point)| (t, s, p, point))
+            })
+    })
+}
+
+/// Describe a point of a track, with its indices (see `enumerate_track_points()`).
+fn format_track_point(t: usize, s: usize, p: usize, point: &gpx::Waypoint) -> eyre::Result<String> {
+    let elevation = point
+        .elevation
+        .map_or("n/a".to_owned(), |elevation| format!("{elevation} m"));
+    let time = match point.time {
+        Some(time) => time.format()?,
+        None => "n/a".to_owned(),
+    };
+
+    Ok(format!(
+        "Track #{t} / Segment #{s} / Point #{p}: lat = {}, lon = {}, elevation = {elevation}, time = {time}",
+        point.point().y(),
+        point.point().x()
+    ))
+}
+
+/// Print the ratio of the track points of each file that are within `tolerance_m` of the tracks of the other file.
+pub fn overlap(
+    first_file: &impl AsRef<Path>,
+    second_file: &impl AsRef<Path>,
+    tolerance_m: f64,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    let files = [first_file.as_ref(), second_file.as_ref()];
+    check_files(&files)?;
+
+    let gpxs = files
+        .iter()
+        .map(|file| read_gpx_file(file, read_options))
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let segments = gpxs
+        .iter()
+        .map(|gpx| {
+            gpx.tracks
+                .iter()
+                .flat_map(|track| &track.segments)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    for (i, j) in [(0, 1), (1, 0)] {
+        let points = segments[i]
+            .iter()
+            .flat_map(|segment| &segment.points)
+            .collect::<Vec<_>>();
+
+        if points.is_empty() {
+            println!("'{}' has no track points", files[i].display());
+            continue;
+        }
+
+        let ratio = compute_overlap_with_lines(&points, &segments[j], tolerance_m);
+        println!(
+            "'{}': {:.1} % of the {} track points are within {tolerance_m} m of the tracks of '{}'",
+            files[i].display(),
+            ratio * 100.0,
+            points.len(),
+            files[j].display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Print the `count` points of the tracks of each file that are the closest to a location, from the closest.
+pub fn nearest(
+    files: &[impl AsRef<Path>],
+    lat: f64,
+    lon: f64,
+    count: usize,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let target = gpx::Waypoint::new((lon, lat).into());
+
+    for file in files {
+        let gpx = read_gpx_file(file, read_options)?;
+
+        if files.len() > 1 {
+            println!("==> {} <==", file.as_ref().display());
+        }
+
+        let mut points = enumerate_track_points(&gpx)
+            .map(|(t, s, p, point)| (distance(&target, point), t, s, p, point))
+            .collect::<Vec<_>>();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        if points.is_empty() {
+            println!("No track points in '{}'", file.as_ref().display());
+        }
+
+        for (distance_m, t, s, p, point) in points.into_iter().take(count) {
+            println!(
+                "{}, distance = {distance_m:.1} m",
+                format_track_point(t, s, p, point)?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn head(
+    files: &[impl AsRef<Path>],
+    count: usize,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    print_points(files, count, false, read_options)
+}
+
+pub fn tail(
+    files: &[impl AsRef<Path>],
+    count: usize,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    print_points(files, count, true, read_options)
+}
+
+/// Options for inverting files.
+#[derive(Clone, Debug, Default)]
+pub struct InvertOptions {
+    /// Only invert the order of the segments of each track:
+    /// the tracks and the points inside each segment keep their order.
+    pub segments_only: bool,
+    /// Skip the files without tracks instead of writing them unchanged.
+    pub skip_empty: bool,
+    /// Print the first and last points of each file before and after inverting,
+    /// to check that the direction is flipped as intended.
+    pub report: bool,
+}
+
+/// Describe the first and last track points of GPX data, e.g. "from (45, 5) to (45.02, 5.02)".
+fn describe_end_points(gpx: &gpx::Gpx) -> String {
+    match get_end_points(gpx, false) {
+        Some((first, last)) => format!(
+            "from ({}, {}) to ({}, {})",
+            first.point().y(),
+            first.point().x(),
+            last.point().y(),
+            last.point().x()
+        ),
+        None => "no track points".to_owned(),
+    }
+}
+
+/// Invert each track of each file (see `InvertOptions`).
+/// Files without tracks are written unchanged, or skipped with `options.skip_empty`.
+pub fn invert(
+    files: &[impl AsRef<Path>],
+    options: &InvertOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let InvertOptions {
+        segments_only,
+        skip_empty,
+        report,
+    } = *options;
+
+    let action = if segments_only {
+        Action::InvertSegments
+    } else {
+        Action::Invert
+    };
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, action, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        if warn_if_no_tracks(&gpx, &in_file, "invert") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        let mut tracks_extensions = load_track_extensions(&in_file)?;
+        if !segments_only {
+            // To follow the tracks.
+            tracks_extensions.reverse();
+        }
+
+        let before = report.then(|| describe_end_points(&gpx));
+
+        transform::Invert { segments_only }.apply(&mut gpx)?;
+
+        if let Some(before) = before {
+            let file = in_file.as_ref().display();
+            println!("'{file}': before inverting: {before}");
+            println!("'{file}': after inverting: {}", describe_end_points(&gpx));
+        }
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+pub fn invert_all(
+    directory: &impl AsRef<Path>,
+    filter: &FileFilter,
+    sort: FileSort,
+    error_on_empty: bool,
+    options: &InvertOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_directory(directory)?;
+    let files = list_gpx_files(directory, filter, sort)?;
+
+    if is_empty_directory(&files, directory, error_on_empty)? {
+        return Ok(());
+    }
+
+    invert(&files, options, read_options, write_options)
+}
+
+/// Name to give to the data written in an output file.
+#[derive(Clone)]
+pub enum OutputName {
+    /// Use the stem of the output file.
+    FromFilename,
+    Custom(String),
+}
+
+impl OutputName {
+    fn resolve(&self, out_file: &Path) -> String {
+        match self {
+            OutputName::FromFilename => out_file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            OutputName::Custom(name) => name.clone(),
+        }
+    }
+}
+
+/// Set the name of the GPX data, in its metadata.
+fn set_name(gpx: &mut gpx::Gpx, name: &OutputName, out_file: &Path) {
+    gpx.metadata.get_or_insert_with(Default::default).name = Some(name.resolve(out_file));
+}
+
+/// Options for `merge()` and `merge_all()`.
+#[derive(Clone, Default)]
+pub struct MergeOptions {
+    /// Also merge the routes of the files, as if they were tracks.
+    pub routes_as_tracks: bool,
+    /// Copy the routes of the files to the merged data, as routes (with their names, descriptions...),
+    /// in the order of the files. `routes_as_tracks` takes precedence.
+    pub include_routes: bool,
+    /// Put the points of each file in a segment of their own (instead of keeping the segments of the files).
+    pub segment_per_file: bool,
+    /// Keep the tracks of the files as separate tracks, with their names, descriptions, links...
+    /// instead of merging all their segments into a single track.
+    /// `segment_per_file` and `max_gap` have no effect in this case.
+    pub keep_tracks: bool,
+    /// Copy the metadata (author, copyright, keywords...) of this file to the merged data,
+    /// except its time and bounds, which describe the file and not the merged data.
+    /// Nothing is copied when the file has no metadata. `name` takes precedence over the name of the metadata.
+    pub metadata_from: Option<PathBuf>,
+    /// Shift the times of the merged points so that the earliest one is this time (see `transform::RebaseTime`).
+    pub rebase_time: Option<OffsetDateTime>,
+    /// Name of the merged data.
+    pub name: Option<OutputName>,
+    /// Comment of the merged track (of every track, with `keep_tracks`).
+    pub comment: Option<String>,
+    /// Description of the merged track (of every track, with `keep_tracks`).
+    pub description: Option<String>,
+    /// Format of the output file, which replaces its extension.
+    /// When not set, the format is guessed from the extension of the output file.
+    pub output_format: Option<OutputFormat>,
+    /// Write a JSON file listing the merged files, next to the output file.
+    pub manifest: Option<PathBuf>,
+    /// Skip the files that cannot be read (e.g. invalid GPX), instead of failing.
+    pub skip_invalid: bool,
+    /// Skip the files whose tracks are shorter than this distance (in meters), such as accidental recordings.
+    pub min_length_m: Option<f64>,
+    /// Only keep one of the tracks that are duplicates of each other (see `are_duplicate_tracks()`).
+    pub dedup_tracks: bool,
+    /// Order of the files in the merged data.
+    pub sort: FileSort,
+    /// Join the first segment of each file to the last segment of the previous file
+    /// when the gap between the end of a file and the start of the next one is at most this duration.
+    /// A new segment is started otherwise. The other segments of the files are kept.
+    /// Unless `sort` says otherwise, the files are ordered by time.
+    pub max_gap: Option<std::time::Duration>,
+    /// Write a merged file per day instead of a single one, grouping the files by the date (in UTC) of their first point.
+    /// The date is added to the names of the output file and of the manifest, e.g. `merged-2024-06-01.gpx`.
+    /// The files without times are merged in an `undated` file.
+    pub per_day: bool,
+    /// Write a merged file per chunk of this number of files instead of a single one (see `merge_chunks()`),
+    /// e.g. `merged-001.gpx`, `merged-002.gpx`... The last chunk may have fewer files.
+    /// `per_day` has no effect in this case.
+    pub chunk_size: Option<usize>,
+    /// Add a waypoint at the start of each segment but the first (see `transform::SegmentBreakMarkers`).
+    pub segment_break_markers: bool,
+    /// Add "Start" and "Finish" waypoints at the ends of the merged data (see `transform::StartFinishMarkers`).
+    pub start_finish_markers: bool,
+    /// Add a waypoint with the distance, the elevation gain and the duration of the merged data in its description
+    /// (see `make_summary_waypoint()`).
+    pub summary_waypoint: bool,
+    /// Add a waypoint at every N-th track point of the merged data (see `transform::CheckpointMarkers`).
+    pub checkpoint_every: Option<usize>,
+    /// Maximum number of segments of the merged tracks, since some tools choke on tracks with hundreds of segments.
+    /// The excess segments are concatenated to the last allowed one (see `limit_segments()`),
+    /// unless `strict_max_segments` is set, in which case the merge fails.
+    pub max_segments: Option<usize>,
+    /// Fail instead of concatenating segments, when a merged track has more than `max_segments` segments.
+    pub strict_max_segments: bool,
+    /// Decimate the merged tracks so that they have about this number of points in total
+    /// (see `decimate_tracks_to_budget()`).
+    pub max_points: Option<usize>,
+    /// Remove the merged points closer than this distance (in meters) to a point kept before them
+    /// (see `transform::DedupPoints`), where the recordings overlap.
+    pub dedup_points_m: Option<f64>,
+    /// Print the distance between the end of each file and the start of the next one,
+    /// with a warning when it is more than this distance (in meters), e.g. when files are in the wrong order.
+    pub check_continuity_m: Option<f64>,
+}
+
+/// Get the first and the last points of the tracks (and of the routes, with `with_routes`), in the merge order.
+fn get_end_points(gpx: &gpx::Gpx, with_routes: bool) -> Option<(&gpx::Waypoint, &gpx::Waypoint)> {
+    let track_points = gpx
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points);
+    let route_points = gpx
+        .routes
+        .iter()
+        .filter(|_| with_routes)
+        .flat_map(|route| &route.points);
+
+    let mut points = track_points.chain(route_points);
+    let first = points.next()?;
+    Some((first, points.last().unwrap_or(first)))
+}
+
+/// Print the distance between the end of each file and the start of the next one, in the merge order,
+/// with a warning when it is more than `max_gap_m`.
+fn check_continuity(files: &[PathBuf], gpxs: &[gpx::Gpx], with_routes: bool, max_gap_m: f64) {
+    let ends = zip(files, gpxs)
+        .filter_map(|(file, gpx)| Some((file, get_end_points(gpx, with_routes)?)))
+        .collect::<Vec<_>>();
+
+    for pair in ends.windows(2) {
+        let ((file, (_, end)), (next_file, (start, _))) = (&pair[0], &pair[1]);
+        let gap_m = distance(end, start);
+
+        if gap_m > max_gap_m {
+            println!(
+                "Warning: {gap_m:.0} m between the end of '{}' and the start of '{}' (more than {max_gap_m} m), are they in the right order?",
+                file.display(),
+                next_file.display()
+            );
+        } else {
+            println!(
+                "{gap_m:.0} m between the end of '{}' and the start of '{}'",
+                file.display(),
+                next_file.display()
+            );
+        }
+    }
+}
+
+/// Concatenate the segments of a track after the `max_segments`-th one into it (with at least one segment).
+/// Return the number of segments that were concatenated.
+fn limit_segments(track: &mut gpx::Track, max_segments: usize) -> usize {
+    let max_segments = max_segments.max(1);
+    if track.segments.len() <= max_segments {
+        return 0;
+    }
+
+    let excess = track.segments.split_off(max_segments);
+    let excess_count = excess.len();
+    let last = track
+        .segments
+        .last_mut()
+        .expect("The track has at least one segment");
+    last.points
+        .extend(excess.into_iter().flat_map(|segment| segment.points));
+
+    excess_count
+}
+
+/// Maximum distance between two points to consider them as the same point, when looking for duplicate tracks.
+const DUPLICATE_TOLERANCE_M: f64 = 10.0;
+
+/// Minimum ratio of the points of a track that must be found in another track to consider them duplicates.
+const DUPLICATE_MIN_OVERLAP: f64 = 0.95;
+
+/// Size of the cells of the grid used to look for close points, in degrees (about 111 meters of latitude,
+/// but less of longitude towards the poles, see `get_grid_cells_near()`).
+const DUPLICATE_GRID_STEP_DEG: f64 = 0.001;
+
+fn get_grid_cell(point: &gpx::Waypoint) -> (i64, i64) {
+    get_grid_cell_of(
+        point.point().y(),
+        point.point().x(),
+        DUPLICATE_GRID_STEP_DEG,
+    )
+}
+
+/// Get the cell of a grid of `step_deg` degrees that contains a position.
+/// The longitude is wrapped (see `wrap_longitude()`), so that a position has a single cell.
+fn get_grid_cell_of(lat: f64, lon: f64, step_deg: f64) -> (i64, i64) {
+    (
+        (lat / step_deg).floor() as i64,
+        (wrap_longitude(lon) / step_deg).floor() as i64,
+    )
+}
+
+/// Get the size of the cells of a grid to find the points within `tolerance_m` of others:
+/// `DUPLICATE_GRID_STEP_DEG`, or larger for large tolerances, so that a point is only searched in a few cells.
+fn get_grid_step(tolerance_m: f64) -> f64 {
+    DUPLICATE_GRID_STEP_DEG.max((tolerance_m / EARTH_RADIUS_M).to_degrees())
+}
+
+/// Get the cells of a grid of `step_deg` degrees that are within `tolerance_m` of a box, given by its corners
+/// (both are the same for a point).
+///
+/// The longitudes are wrapped as in `get_grid_cell_of()`: a box near the antimeridian (or beyond it,
+/// e.g. from 179 to 181) gets the cells on both sides of it.
+fn get_grid_cells_near(
+    (min_lat, min_lon): (f64, f64),
+    (max_lat, max_lon): (f64, f64),
+    tolerance_m: f64,
+    step_deg: f64,
+) -> impl Iterator<Item = (i64, i64)> {
+    let tolerance_lat_deg = (tolerance_m / EARTH_RADIUS_M).to_degrees();
+    // The degrees of longitude get shorter towards the poles.
+    let max_abs_lat = min_lat.abs().max(max_lat.abs());
+    let tolerance_lon_deg = tolerance_lat_deg / max_abs_lat.to_radians().cos().max(0.01);
+
+    let get_cell = |deg: f64| (deg / step_deg).floor() as i64;
+    let lat_cells = get_cell(min_lat - tolerance_lat_deg)..=get_cell(max_lat + tolerance_lat_deg);
+
+    // The ranges of longitudes, split at the antimeridian.
+    let width = (max_lon - min_lon) + 2.0 * tolerance_lon_deg;
+    let west = wrap_longitude(min_lon - tolerance_lon_deg);
+    let lon_ranges = if width >= 360.0 {
+        vec![(-180.0, 180.0)]
+    } else if west + width < 180.0 {
+        vec![(west, west + width)]
+    } else {
+        vec![(west, 180.0), (-180.0, west + width - 360.0)]
+    };
+    let lon_cells = lon_ranges
+        .into_iter()
+        .map(|(west, east)| get_cell(west)..=get_cell(east))
+        .collect::<Vec<_>>();
+
+    lat_cells.flat_map(move |lat| {
+        lon_cells
+            .clone()
+            .into_iter()
+            .flatten()
+            .map(move |lon| (lat, lon))
+    })
+}
+
+/// Compute the ratio of `points` that are close to (at least) one of `others`.
+fn compute_overlap(points: &[&gpx::Waypoint], others: &[&gpx::Waypoint]) -> f64 {
+    let mut grid: HashMap<(i64, i64), Vec<&gpx::Waypoint>> = HashMap::new();
+    for &other in others {
+        grid.entry(get_grid_cell(other)).or_default().push(other);
+    }
+
+    let is_close_to_others = |point: &gpx::Waypoint| {
+        let position = (point.point().y(), point.point().x());
+        get_grid_cells_near(
+            position,
+            position,
+            DUPLICATE_TOLERANCE_M,
+            DUPLICATE_GRID_STEP_DEG,
+        )
+        .filter_map(|cell| grid.get(&cell))
+        .flatten()
+        .any(|other| distance(point, other) <= DUPLICATE_TOLERANCE_M)
+    };
+
+    let close_count = points
+        .iter()
+        .filter(|point| is_close_to_others(point))
+        .count();
+    close_count as f64 / points.len() as f64
+}
+
+/// Project a point on a plane tangent to the Earth at `origin`, in meters (east, north) from `origin`.
+/// It is accurate enough for the points near `origin`, such as the neighbors in a track.
+fn project_on_plane(origin: &gpx::Waypoint, point: &gpx::Waypoint) -> (f64, f64) {
+    let cos_lat = origin.point().y().to_radians().cos();
+    (
+        wrap_longitude(point.point().x() - origin.point().x()).to_radians()
+            * cos_lat
+            * EARTH_RADIUS_M,
+        (point.point().y() - origin.point().y()).to_radians() * EARTH_RADIUS_M,
+    )
+}
+
+/// Compute the distance (in meters) between a point and the line from `a` to `b`.
+///
+/// The points are projected on a plane tangent to the Earth at `point` (see `project_on_plane()`);
+/// it is accurate enough for the lines between the points of tracks, which are short.
+fn distance_to_line(point: &gpx::Waypoint, a: &gpx::Waypoint, b: &gpx::Waypoint) -> f64 {
+    let ((ax, ay), (bx, by)) = (project_on_plane(point, a), project_on_plane(point, b));
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_squared = dx * dx + dy * dy;
+
+    // Position of the closest point of the line, from 0 at `a` to 1 at `b`.
+    let ratio = if length_squared > 0.0 {
+        (-(ax * dx + ay * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    (ax + ratio * dx).hypot(ay + ratio * dy)
+}
+
+/// Compute the area (in square meters) of the triangle formed by three points, projected on a plane (see `project_on_plane()`).
+fn triangle_area(a: &gpx::Waypoint, b: &gpx::Waypoint, c: &gpx::Waypoint) -> f64 {
+    let ((bx, by), (cx, cy)) = (project_on_plane(a, b), project_on_plane(a, c));
+    (bx * cy - by * cx).abs() / 2.0
+}
+
+/// An index of the lines between the points of polylines (segments of tracks, routes...),
+/// to find quickly whether points are within a tolerance of them.
+///
+/// The lines are put in the cells of a grid (see `get_grid_step()`) near them, so that only the lines
+/// of the cell of a point are checked.
+/// The cells are the ones along each line, not the ones of its box, so that a long diagonal line
+/// (e.g. between the sparse points of a planned route) is only in a few cells per degree.
+struct LineIndex<'a> {
+    grid: HashMap<(i64, i64), Vec<(&'a gpx::Waypoint, &'a gpx::Waypoint)>>,
+    tolerance_m: f64,
+    step_deg: f64,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(polylines: impl IntoIterator<Item = &'a [gpx::Waypoint]>, tolerance_m: f64) -> Self {
+        let step_deg = get_grid_step(tolerance_m);
+
+        let mut grid: HashMap<(i64, i64), Vec<_>> = HashMap::new();
+        for points in polylines {
+            // A polyline with a single point is a line of length 0.
+            let lines = points
+                .windows(2)
+                .map(|pair| (&pair[0], &pair[1]))
+                .chain(match points {
+                    [point] => Some((point, point)),
+                    _ => None,
+                });
+
+            for (a, b) in lines {
+                let (lat_a, lon_a) = (a.point().y(), a.point().x());
+                // The short way, across the antimeridian if needed (as `distance_to_line()` does).
+                let (lat_b, lon_b) = (b.point().y(), lon_a + wrap_longitude(b.point().x() - lon_a));
+
+                // The line is cut in pieces of at most a cell, and each piece is put in the cells near it.
+                let piece_count = ((lat_b - lat_a).abs().max((lon_b - lon_a).abs()) / step_deg)
+                    .ceil()
+                    .max(1.0);
+                let at = |ratio: f64| {
+                    (
+                        lat_a + (lat_b - lat_a) * ratio,
+                        lon_a + (lon_b - lon_a) * ratio,
+                    )
+                };
+
+                let mut cells = HashSet::new();
+                for i in 0..piece_count as usize {
+                    let (start, end) =
+                        (at(i as f64 / piece_count), at((i + 1) as f64 / piece_count));
+                    cells.extend(get_grid_cells_near(
+                        (start.0.min(end.0), start.1.min(end.1)),
+                        (start.0.max(end.0), start.1.max(end.1)),
+                        tolerance_m,
+                        step_deg,
+                    ));
+                }
+                for cell in cells {
+                    grid.entry(cell).or_default().push((a, b));
+                }
+            }
+        }
+
+        LineIndex {
+            grid,
+            tolerance_m,
+            step_deg,
+        }
+    }
+
+    /// Check if a point is within the tolerance of (at least) one of the lines.
+    fn is_near(&self, point: &gpx::Waypoint) -> bool {
+        let cell = get_grid_cell_of(point.point().y(), point.point().x(), self.step_deg);
+        self.grid.get(&cell).is_some_and(|lines| {
+            lines
+                .iter()
+                .any(|(a, b)| distance_to_line(point, a, b) <= self.tolerance_m)
+        })
+    }
+}
+
+/// Compute the ratio of `points` that are within `tolerance_m` of (at least) one of the lines between the points
+/// of `segments` (see `LineIndex`).
+fn compute_overlap_with_lines(
+    points: &[&gpx::Waypoint],
+    segments: &[&gpx::TrackSegment],
+    tolerance_m: f64,
+) -> f64 {
+    let index = LineIndex::new(
+        segments.iter().map(|segment| segment.points.as_slice()),
+        tolerance_m,
+    );
+
+    let close_count = points.iter().filter(|point| index.is_near(point)).count();
+    close_count as f64 / points.len() as f64
+}
+
+/// Check if two tracks are (nearly) identical.
+///
+/// They are if they have the same number of points and their points are pairwise within `DUPLICATE_TOLERANCE_M`,
+/// or if almost all the points of each track (see `DUPLICATE_MIN_OVERLAP`) are close to a point of the other track.
+/// The latter detects the same ride recorded by two devices, or exported twice with different settings.
+fn are_duplicate_tracks(a: &gpx::Track, b: &gpx::Track) -> bool {
+    let points_a = a
+        .segments
+        .iter()
+        .flat_map(|segment| &segment.points)
+        .collect::<Vec<_>>();
+    let points_b = b
+        .segments
+        .iter()
+        .flat_map(|segment| &segment.points)
+        .collect::<Vec<_>>();
+
+    if points_a.is_empty() || points_b.is_empty() {
+        return false;
+    }
+
+    let have_same_points = points_a.len() == points_b.len()
+        && zip(&points_a, &points_b).all(|(a, b)| distance(a, b) <= DUPLICATE_TOLERANCE_M);
+
+    have_same_points
+        || (compute_overlap(&points_a, &points_b) >= DUPLICATE_MIN_OVERLAP
+            && compute_overlap(&points_b, &points_a) >= DUPLICATE_MIN_OVERLAP)
+}
+
+/// Remove the tracks that are duplicates of a track that comes before them (in the same file or in a previous file).
+/// Return the number of removed tracks.
+fn remove_duplicate_tracks(files: &[impl AsRef<Path>], gpxs: &mut [gpx::Gpx]) -> usize {
+    // The indices of the duplicates, as (file, track), found before removing anything to avoid copying the tracks.
+    let mut duplicates = HashSet::new();
+    let mut kept_tracks: Vec<(usize, usize, &gpx::Track)> = Vec::new();
+
+    for (f, gpx) in gpxs.iter().enumerate() {
+        for (t, track) in gpx.tracks.iter().enumerate() {
+            let original = kept_tracks
+                .iter()
+                .find(|(_, _, kept)| are_duplicate_tracks(kept, track));
+
+            match original {
+                Some((original_f, original_t, _)) => {
+                    println!(
+                        "Track #{t} of '{}' is a duplicate of track #{original_t} of '{}', it is not merged",
+                        files[f].as_ref().display(),
+                        files[*original_f].as_ref().display()
+                    );
+                    duplicates.insert((f, t));
+                }
+                None => kept_tracks.push((f, t, track)),
+            }
+        }
+    }
+
+    for (f, gpx) in gpxs.iter_mut().enumerate() {
+        let mut t = 0;
+        gpx.tracks.retain(|_| {
+            t += 1;
+            !duplicates.contains(&(f, t - 1))
+        });
+    }
+
+    duplicates.len()
+}
+
+/// Get the time of the first point (of the first segment of the first track) that has a time.
+fn get_start_time(gpx: &gpx::Gpx) -> Option<OffsetDateTime> {
+    gpx.tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points)
+        .find_map(|point| point.time)
+        .map(OffsetDateTime::from)
+}
+
+/// Join the first segment of each file to the last segment of the previous file,
+/// when the time gap between them is at most `max_gap` (and not negative: the files that overlap in time
+/// are not joined, since the times of the joined segment would go backwards).
+/// `files_segments` contains the segments of each file; the other segments are kept as they are
+/// (except the empty ones, which are removed), so that the pauses inside the files are kept.
+fn join_segments_by_gap(
+    files_segments: Vec<Vec<gpx::TrackSegment>>,
+    max_gap: std::time::Duration,
+) -> Vec<gpx::TrackSegment> {
+    let mut segments: Vec<gpx::TrackSegment> = Vec::new();
+
+    for file_segments in files_segments {
+        let mut file_segments = file_segments
+            .into_iter()
+            .filter(|segment| !segment.points.is_empty());
+
+        let Some(first) = file_segments.next() else {
+            continue;
+        };
+
+        let end = segments
+            .last()
+            .and_then(|segment| segment.points.last())
+            .and_then(|point| point.time);
+        let start = first.points.first().and_then(|point| point.time);
+
+        let is_close = match (end, start) {
+            (Some(end), Some(start)) => {
+                let gap = OffsetDateTime::from(start) - OffsetDateTime::from(end);
+                !gap.is_negative() && gap.unsigned_abs() <= max_gap
+            }
+            _ => false,
+        };
+
+        match segments.last_mut() {
+            Some(segment) if is_close => segment.points.extend(first.points),
+            _ => segments.push(first),
+        }
+        segments.extend(file_segments);
+    }
+
+    segments
+}
+
+/// Merge files into `output_file`.
+///
+/// The order of the merged data is deterministic:
+/// - The files are merged in the order of `files`, unless `options.sort` tells otherwise
+///   (or `options.max_gap` is set, which orders them by time). All the sorts are stable,
+///   so files with equal keys stay in the order of `files`.
+/// - The tracks of each file, their segments and their points stay in the order of the file.
+///   The routes come after the tracks of their file, with `options.routes_as_tracks`.
+///   With `options.include_routes`, the routes are in the order of the files too.
+/// - With `options.dedup_tracks`, the first of the duplicate tracks (in the above order) is kept.
+/// - With `options.per_day`, the files of each day are merged in the same order.
+/// - With `options.chunk_size`, the files are split into chunks in this order.
+pub fn merge(
+    files: &[impl AsRef<Path>],
+    output_file: &impl AsRef<Path>,
+    options: &MergeOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+    // Before loading the files, to fail early if it is not a GPX file.
+    if let Some(file) = &options.metadata_from {
+        check_files(&[file])?;
+    }
+
+    println!("Merging {} files...", files.len());
+
+    let mut files_gpxs = Vec::new();
+    let mut skipped_files = Vec::new();
+    for file in files {
+        match load_gpx(file, read_options) {
+            Ok(gpx) => files_gpxs.push((file.as_ref().to_path_buf(), gpx)),
+            Err(err) if options.skip_invalid => {
+                println!(
+                    "Warning: skipping '{}' since it cannot be read: {err}",
+                    file.as_ref().display()
+                );
+                skipped_files.push(file.as_ref());
+            }
+            Err(err) => {
+                return Err(eyre!("Cannot read '{}': {err}", file.as_ref().display()));
+            }
+        }
+    }
+
+    if !skipped_files.is_empty() {
+        println!(
+            "{} file(s) skipped since they cannot be read:",
+            skipped_files.len()
+        );
+        for file in &skipped_files {
+            println!("- {}", file.display());
+        }
+
+        if files_gpxs.is_empty() {
+            return Err(eyre!("None of the files can be read, nothing to merge"));
+        }
+    }
+
+    if let Some(min_length_m) = options.min_length_m {
+        files_gpxs.retain(|(file, gpx)| {
+            let length_m = compute_tracks_distance(gpx);
+            let is_long_enough = length_m >= min_length_m;
+            if !is_long_enough {
+                println!(
+                    "Skipping '{}' since its tracks are {length_m:.1} m long, less than {min_length_m} m",
+                    file.display()
+                );
+            }
+            is_long_enough
+        });
+
+        if files_gpxs.is_empty() {
+            return Err(eyre!(
+                "None of the files are at least {min_length_m} m long, nothing to merge"
+            ));
+        }
+    }
+
+    if let Some(chunk_size) = options.chunk_size {
+        return merge_chunks(
+            files_gpxs,
+            output_file.as_ref(),
+            chunk_size,
+            options,
+            read_options,
+            write_options,
+        );
+    }
+
+    if !options.per_day {
+        return merge_loaded(
+            files_gpxs,
+            output_file.as_ref(),
+            options,
+            read_options,
+            write_options,
+        );
+    }
+
+    // Ordered by date, the undated files being first.
+    let mut days: BTreeMap<Option<time::Date>, Vec<_>> = BTreeMap::new();
+    for (file, gpx) in files_gpxs {
+        let date = get_start_time(&gpx).map(|time| time.to_offset(time::UtcOffset::UTC).date());
+        days.entry(date).or_default().push((file, gpx));
+    }
+
+    println!("The files span {} day(s)", days.len());
+
+    for (date, files_gpxs) in days {
+        let suffix = date.map_or("undated".to_owned(), |date| date.to_string());
+        let options = MergeOptions {
+            manifest: options
+                .manifest
+                .as_ref()
+                .map(|manifest| add_suffix_to_file_stem(manifest, &suffix)),
+            ..options.clone()
+        };
+
+        merge_loaded(
+            files_gpxs,
+            &add_suffix_to_file_stem(output_file.as_ref(), &suffix),
+            &options,
+            read_options,
+            write_options,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Merge files that are already loaded by chunks of `chunk_size` files (in the order of the merge),
+/// numbered from 1 in the names of the output files and of the manifests, e.g. `merged-001.gpx`.
+fn merge_chunks(
+    mut files_gpxs: Vec<(PathBuf, gpx::Gpx)>,
+    output_file: &Path,
+    chunk_size: usize,
+    options: &MergeOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    if chunk_size == 0 {
+        return Err(eyre!("The number of files per chunk should be positive"));
+    }
+
+    sort_loaded_files(&mut files_gpxs, options)?;
+
+    let chunk_count = files_gpxs.len().div_ceil(chunk_size);
+    // At least 3 digits, so that the names are sorted like the chunks.
+    let width = chunk_count.to_string().len().max(3);
+    println!("Merging {chunk_count} chunk(s) of at most {chunk_size} files");
+
+    let mut files_gpxs = files_gpxs.into_iter();
+    for chunk in 1..=chunk_count {
+        let suffix = format!("{chunk:0width$}");
+        let options = MergeOptions {
+            manifest: options
+                .manifest
+                .as_ref()
+                .map(|manifest| add_suffix_to_file_stem(manifest, &suffix)),
+            ..options.clone()
+        };
+
+        merge_loaded(
+            files_gpxs.by_ref().take(chunk_size).collect(),
+            &add_suffix_to_file_stem(output_file, &suffix),
+            &options,
+            read_options,
+            write_options,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Sort files that are already loaded in the order of the merge (see `merge()`).
+fn sort_loaded_files(
+    files_gpxs: &mut Vec<(PathBuf, gpx::Gpx)>,
+    options: &MergeOptions,
+) -> eyre::Result<()> {
+    let mut sort = options.sort;
+    if options.max_gap.is_some() && sort.by.is_none() {
+        sort.by = Some(FileOrder::Time);
+    }
+    sort_files(
+        files_gpxs,
+        sort,
+        |(file, _)| file,
+        |(_, gpx)| Ok(get_start_time(gpx)),
+    )
+}
+
+/// Merge files that are already loaded, as `merge()` does.
+fn merge_loaded(
+    mut files_gpxs: Vec<(PathBuf, gpx::Gpx)>,
+    output_file: &Path,
+    options: &MergeOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    // Before merging, to fail before writing anything if the file cannot be read.
+    let metadata = match &options.metadata_from {
+        Some(file) => load_gpx(file, read_options)?
+            .metadata
+            .map(|metadata| gpx::Metadata {
+                time: None,
+                bounds: None,
+                ..metadata
+            }),
+        None => None,
+    };
+
+    sort_loaded_files(&mut files_gpxs, options)?;
+
+    let (files, mut gpxs): (Vec<_>, Vec<_>) = files_gpxs.into_iter().unzip();
+
+    for (file, gpx) in zip(&files, &gpxs) {
+        // Routes are merged too in this case.
+        let has_routes_to_merge =
+            (options.routes_as_tracks || options.include_routes) && !gpx.routes.is_empty();
+
+        if !has_routes_to_merge {
+            warn_if_no_tracks(gpx, file, "merge");
+        }
+    }
+
+    // Before the removal of duplicates, to describe the input files as they are.
+    let inputs = zip(&files, &gpxs)
+        .map(|(file, gpx)| {
+            serde_json::json!({
+                "path": file.display().to_string(),
+                "points": count_points(gpx),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if options.dedup_tracks {
+        let removed_count = remove_duplicate_tracks(&files, &mut gpxs);
+        println!("{removed_count} duplicate track(s) found");
+    }
+
+    if let Some(max_gap_m) = options.check_continuity_m {
+        check_continuity(&files, &gpxs, options.routes_as_tracks, max_gap_m);
+    }
+
+    let routes = if options.include_routes && !options.routes_as_tracks {
+        gpxs.iter_mut()
+            .flat_map(|gpx| std::mem::take(&mut gpx.routes))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // The loaded data is consumed, so that the points are moved to the merged tracks instead of being copied.
+    // Hence, the peak memory usage is about the size of the loaded data, not twice this size.
+    let files_tracks = gpxs.into_iter().map(|gpx| {
+        let mut tracks = gpx.tracks;
+        if options.routes_as_tracks {
+            tracks.extend(gpx.routes.into_iter().map(route_into_track));
+        }
+        tracks
+    });
+
+    let mut tracks: Vec<gpx::Track> = if options.keep_tracks {
+        files_tracks.flatten().collect()
+    } else {
+        let files_segments = files_tracks
+            .map(|tracks| {
+                let mut segments = tracks
+                    .into_iter()
+                    .flat_map(|track| track.segments)
                     .collect::<Vec<_>>();
+
+                if options.segment_per_file {
+                    let points = segments.into_iter().flat_map(|segment| segment.points);
+                    segments = vec![gpx::TrackSegment {
+                        points: points.collect(),
+                    }];
+                }
+
+                segments
+            })
+            .collect::<Vec<_>>();
+
+        let segments = match options.max_gap {
+            Some(max_gap) => join_segments_by_gap(files_segments, max_gap),
+            None => files_segments.into_iter().flatten().collect(),
+        };
+
+        vec![gpx::Track {
+            segments,
+            ..Default::default()
+        }]
+    };
+
+    if let Some(max_segments) = options.max_segments {
+        for (t, track) in tracks.iter_mut().enumerate() {
+            let segment_count = track.segments.len();
+            if segment_count > max_segments && options.strict_max_segments {
+                return Err(eyre!(
+                    "Merged track #{t} would have {segment_count} segments, more than {max_segments}"
+                ));
+            }
+
+            let concatenated_count = limit_segments(track, max_segments);
+            if concatenated_count > 0 {
+                println!(
+                    "Merged track #{t}: {concatenated_count} segment(s) concatenated to keep at most {max_segments}"
+                );
+            }
+            println!("Merged track #{t}: {} segment(s)", track.segments.len());
+        }
+    }
+
+    for track in &mut tracks {
+        if let Some(comment) = &options.comment {
+            track.comment = Some(comment.clone());
+        }
+        if let Some(description) = &options.description {
+            track.description = Some(description.clone());
+        }
+    }
+
+    let mut gpx = gpx::Gpx {
+        creator: Some(get_creator()),
+        version: gpx::GpxVersion::Gpx11,
+        metadata,
+        tracks,
+        routes,
+        ..Default::default()
+    };
+
+    // Before decimating to the budget, so that the budget is shared among the remaining points.
+    if let Some(min_distance_m) = options.dedup_points_m {
+        let point_count = count_points(&gpx);
+        transform::DedupPoints { min_distance_m }.apply(&mut gpx)?;
+        println!(
+            "Removed {} point(s) closer than {min_distance_m} m to another point",
+            point_count - count_points(&gpx)
+        );
+    }
+
+    if let Some(max_points) = options.max_points {
+        let counts = decimate_tracks_to_budget(&mut gpx.tracks, max_points);
+        for (t, (before, after)) in counts.into_iter().enumerate() {
+            println!("Merged track #{t}: {before} -> {after} points");
+        }
+    }
+
+    let (output_file, format) = match options.output_format {
+        Some(format) => (output_file.with_extension(format.extension()), format),
+        None => (
+            output_file.to_path_buf(),
+            OutputFormat::from_path(&output_file).unwrap_or(OutputFormat::Gpx),
+        ),
+    };
+
+    if let Some(start) = options.rebase_time {
+        transform::RebaseTime { start }.apply(&mut gpx)?;
+    }
+
+    if options.segment_break_markers {
+        transform::SegmentBreakMarkers.apply(&mut gpx)?;
+    }
+
+    if let Some(every) = options.checkpoint_every {
+        transform::CheckpointMarkers { every }.apply(&mut gpx)?;
+    }
+
+    if options.start_finish_markers {
+        transform::StartFinishMarkers.apply(&mut gpx)?;
+    }
+
+    if options.summary_waypoint {
+        gpx.waypoints.extend(make_summary_waypoint(&gpx));
+    }
+
+    if let Some(name) = &options.name {
+        set_name(&mut gpx, name, &output_file);
+    }
+
+    save_as(&gpx, &output_file, format, write_options)?;
+
+    if let Some(manifest) = &options.manifest {
+        if write_options.dry_run {
+            println!("Dry run: not saving manifest to '{}'", manifest.display());
+        } else {
+            save_manifest(manifest, &output_file, inputs)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Save a JSON manifest describing how the output file of a merge was produced.
+fn save_manifest(
+    manifest: &Path,
+    output_file: &Path,
+    inputs: Vec<serde_json::Value>,
+) -> eyre::Result<()> {
+    println!("Saving manifest to '{}'...", manifest.display());
+
+    let date = gpx::Time::from(OffsetDateTime::now_utc()).format()?;
+    let document = serde_json::json!({
+        "creator": get_creator(),
+        "date": date,
+        "output": output_file.display().to_string(),
+        "inputs": inputs,
+    });
+
+    write_file_atomically(manifest, |writer| {
+        serde_json::to_writer_pretty(writer, &document)?;
+        Ok(())
+    })
+    .map_err(|err| eyre!("Cannot save manifest to '{}': {err}", manifest.display()))
+}
+
+/// Merge the GPX files of a directory into a file in this directory, as `merge()` does.
+///
+/// The files are listed by `list_gpx_files()`, so they are merged by name (comparing the bytes of the names),
+/// unless `options.sort` tells otherwise.
+pub fn merge_all(
+    directory: &impl AsRef<Path>,
+    filter: &FileFilter,
+    error_on_empty: bool,
+    options: &MergeOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_directory(directory)?;
+    // Sorted according to the options by `merge()`.
+    let files = list_gpx_files(directory, filter, FileSort::default())?;
+
+    if is_empty_directory(&files, directory, error_on_empty)? {
+        return Ok(());
+    }
+
+    let output_file = get_merged_file_path(directory, write_options);
+    merge(&files, &output_file, options, read_options, write_options)
+}
+
+/// Get the path of the file merging files in a directory, as `merge_all()` writes it: `merged.gpx`,
+/// with the output suffix or timestamp of `write_options`, if any.
+pub fn get_merged_file_path(directory: &impl AsRef<Path>, write_options: &WriteOptions) -> PathBuf {
+    get_output_file_path(directory, Action::Merge, write_options)
+}
+
+/// Merge the GPX files of a directory by groups, as `merge()` does: the files whose names give the same key
+/// are merged into `<key>.gpx` (or with the extension of `options.output_format`), in this directory.
+/// The output suffix or timestamp of `write_options`, if any, is added to the key, as `get_output_file_path()` does.
+///
+/// The key is the part of the name of a file matched by the first capture group of `pattern`
+/// (or by the whole pattern, if it has no groups); for instance, `^(\d{4}-\d{2}-\d{2})-` groups the files by date prefix.
+/// The files whose names do not match are skipped, with a warning.
+/// The groups are merged in the order of their keys, and the files of each group are merged by name, unless
+/// `options.sort` tells otherwise.
+pub fn group_merge(
+    directory: &impl AsRef<Path>,
+    filter: &FileFilter,
+    error_on_empty: bool,
+    pattern: &str,
+    options: &MergeOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    let regex = regex::Regex::new(pattern)
+        .map_err(|err| eyre!("Invalid regular expression '{pattern}': {err}"))?;
+
+    check_directory(directory)?;
+    // Sorted according to the options by `merge()`.
+    let files = list_gpx_files(directory, filter, FileSort::default())?;
+
+    if is_empty_directory(&files, directory, error_on_empty)? {
+        return Ok(());
+    }
+
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let key = regex
+            .captures(&name)
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+            .map(|key| key.as_str().to_owned());
+
+        match key {
+            Some(key) if !key.is_empty() && !key.contains(['/', '\\']) => {
+                groups.entry(key).or_default().push(file);
+            }
+            Some(key) => println!(
+                "Warning: skipping '{}' since its key '{key}' cannot be used as a file name",
+                file.display()
+            ),
+            None => println!(
+                "Warning: skipping '{}' since its name does not match '{pattern}'",
+                file.display()
+            ),
+        }
+    }
+
+    println!("{} group(s) found", groups.len());
+
+    for (key, files) in groups {
+        let options = MergeOptions {
+            manifest: options
+                .manifest
+                .as_ref()
+                .map(|manifest| add_suffix_to_file_stem(manifest, &key)),
+            ..options.clone()
+        };
+
+        let mut output_file = directory.as_ref().join(format!("{key}.gpx"));
+        // Named after the key rather than after the action, unless the names of the outputs are customized.
+        if write_options.output_suffix.is_some() || write_options.output_timestamp.is_some() {
+            output_file = get_output_file_path(&output_file, Action::Merge, write_options);
+        }
+
+        merge(&files, &output_file, &options, read_options, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// The numbers of tracks, segments and points of GPX data.
+struct ElementCounts {
+    tracks: usize,
+    segments: usize,
+    points: usize,
+}
+
+/// Count the tracks, the segments of all the tracks, and the points of all these segments.
+fn count_elements(gpx: &gpx::Gpx) -> ElementCounts {
+    let segments = gpx.tracks.iter().flat_map(|track| &track.segments);
+
+    ElementCounts {
+        tracks: gpx.tracks.len(),
+        segments: segments.clone().count(),
+        points: segments.map(|segment| segment.points.len()).sum(),
+    }
+}
+
+/// Count the points of all the segments of all the tracks.
+fn count_points(gpx: &gpx::Gpx) -> usize {
+    count_elements(gpx).points
+}
+
+/// Describe how much a number of points has been reduced.
+fn format_reduction(before: usize, after: usize) -> String {
+    if before == 0 {
+        "nothing to reduce".to_owned()
+    } else {
+        let ratio = before as f64 / after.max(1) as f64;
+        let removed = 100.0 * (before - after) as f64 / before as f64;
+        format!("reduced by a ratio of {ratio:.2} ({removed:.1} % removed)")
+    }
+}
+
+/// Tell whether the point at `index` in a segment of `len` points is kept when decimating by `factor_m`,
+/// starting at `offset`: the points at `offset`, `offset + factor_m`, `offset + 2 * factor_m`... are kept.
+/// The last point is always kept so that the segment still ends at the same place.
+fn is_kept_by_decimation(index: usize, len: usize, factor_m: u16, offset: usize) -> bool {
+    (index >= offset && (index - offset).is_multiple_of(factor_m as usize)) || index == len - 1
+}
+
+/// Count the points that would remain after decimating by `factor_m`, starting at `offset`.
+fn count_points_after_decimation(gpx: &gpx::Gpx, factor_m: u16, offset: usize) -> usize {
+    gpx.tracks
+        .iter()
+        .map(|track| count_track_points_after_decimation(track, factor_m, offset))
+        .sum()
+}
+
+/// Count the points of a track that would remain after decimating by `factor_m`, starting at `offset`.
+fn count_track_points_after_decimation(track: &gpx::Track, factor_m: u16, offset: usize) -> usize {
+    track
+        .segments
+        .iter()
+        .map(|segment| count_segment_points_after_decimation(segment, factor_m, offset))
+        .sum()
+}
+
+/// Count the points of a segment that would remain after decimating by `factor_m`, starting at `offset`.
+fn count_segment_points_after_decimation(
+    segment: &gpx::TrackSegment,
+    factor_m: u16,
+    offset: usize,
+) -> usize {
+    let len = segment.points.len();
+    (0..len)
+        .filter(|i| is_kept_by_decimation(*i, len, factor_m, offset))
+        .count()
+}
+
+/// Decimate the points of a segment by `factor_m`, starting at `offset` (see `is_kept_by_decimation()`).
+fn decimate_segment(segment: &mut gpx::TrackSegment, factor_m: u16, offset: usize) {
+    let len = segment.points.len();
+    let mut index = 0;
+    segment.points.retain(|_| {
+        index += 1;
+        is_kept_by_decimation(index - 1, len, factor_m, offset)
+    });
+}
+
+/// Decimate the tracks so that they have about `max_points` points in total.
+/// Each track gets a share of `max_points` proportional to its number of points, so that none dominates,
+/// and is decimated by the smallest factor that fits in its share (the names of the tracks are kept).
+/// Return the numbers of points of each track, before and after.
+fn decimate_tracks_to_budget(tracks: &mut [gpx::Track], max_points: usize) -> Vec<(usize, usize)> {
+    let count_track_points = |track: &gpx::Track| -> usize {
+        track
+            .segments
+            .iter()
+            .map(|segment| segment.points.len())
+            .sum()
+    };
+
+    let counts = tracks.iter().map(count_track_points).collect::<Vec<_>>();
+    let total: usize = counts.iter().sum();
+
+    zip(tracks, counts)
+        .map(|(track, count)| {
+            if total <= max_points {
+                return (count, count);
+            }
+
+            let share = (max_points as f64 * count as f64 / total as f64)
+                .floor()
+                .max(1.0) as usize;
+            let mut factor_m = (count / share).clamp(1, u16::MAX as usize) as u16;
+            while factor_m < u16::MAX
+                && count_track_points_after_decimation(track, factor_m, 0) > share
+            {
+                factor_m += 1;
+            }
+
+            for segment in &mut track.segments {
+                decimate_segment(segment, factor_m, 0);
+            }
+
+            (count, count_track_points(track))
+        })
+        .collect()
+}
+
+/// Options for decimating files, besides the factor.
+#[derive(Clone, Debug, Default)]
+pub struct DecimateOptions {
+    /// Start keeping the points at this index of each segment, instead of the first point (see `transform::Decimate`).
+    pub offset: usize,
+    /// Never keep less than this number of points in a file: the factor is reduced for the files where it would happen.
+    pub min_points: Option<usize>,
+    /// Decimate the segments that would still have more points by a larger factor (see `transform::Decimate`).
+    pub max_points_per_segment: Option<usize>,
+    /// After decimating, keep each point with this probability (see `transform::RandomSample`).
+    pub random_fraction: Option<f64>,
+    /// The seed of the random sampling, which always keeps the same points for the same seed.
+    pub seed: u64,
+    /// After decimating, remove the spikes (see `transform::Despike`).
+    pub despike_m: Option<f64>,
+    /// Skip the files without tracks instead of writing them unchanged.
+    pub skip_empty: bool,
+}
+
+/// Print a table telling, for each decimated file, its number of points before and after,
+/// and the total across all files.
+fn print_decimation_summary(rows: &[(PathBuf, usize, usize)]) {
+    let removed_percent = |before: usize, after: usize| {
+        if before == 0 {
+            0.0
+        } else {
+            100.0 * (before - after) as f64 / before as f64
+        }
+    };
+
+    println!("Original | Kept     | Removed (%) | File");
+    for (file, before, after) in rows {
+        println!(
+            "{before:>8} | {after:>8} | {:>11.1} | {}",
+            removed_percent(*before, *after),
+            file.display()
+        );
+    }
+
+    let before = rows.iter().map(|(_, before, _)| before).sum::<usize>();
+    let after = rows.iter().map(|(_, _, after)| after).sum::<usize>();
+    println!(
+        "{before:>8} | {after:>8} | {:>11.1} | Total ({} file(s))",
+        removed_percent(before, after),
+        rows.len()
+    );
+}
+
+/// Decimate each file by `factor_m`.
+/// With `verbose`, the numbers of points are printed, and the factor of each segment if it is computed per segment;
+/// a table summarizing the points of all the files is printed at the end.
+pub fn decimate(
+    files: &[impl AsRef<Path>],
+    factor_m: u16,
+    options: &DecimateOptions,
+    verbose: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    let DecimateOptions {
+        offset,
+        min_points,
+        max_points_per_segment,
+        random_fraction,
+        seed,
+        despike_m,
+        skip_empty,
+    } = *options;
+
+    check_files(files)?;
+
+    let mut affected_files = Vec::new();
+    let mut summary = Vec::new();
+
+    for in_file in files {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        if warn_if_no_tracks(&gpx, &in_file, "decimate") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        let tracks_extensions = load_track_extensions(&in_file)?;
+
+        // Reduce the factor for this file if decimating would leave too few points.
+        let mut effective_factor_m = factor_m;
+        if let Some(min_points) = min_points {
+            while effective_factor_m > 1
+                && count_points_after_decimation(&gpx, effective_factor_m, offset) < min_points
+            {
+                effective_factor_m -= 1;
+            }
+
+            let point_count = count_points(&gpx);
+            if point_count < min_points {
+                println!(
+                    "Warning: '{}' has only {point_count} points, which is already less than {min_points}",
+                    in_file.as_ref().display()
+                );
+            }
+
+            if effective_factor_m != factor_m {
+                println!(
+                    "Warning: decimating '{}' by {factor_m} would leave less than {min_points} points, decimating by {effective_factor_m} instead",
+                    in_file.as_ref().display()
+                );
+                affected_files.push(in_file.as_ref().to_path_buf());
+            }
+        }
+
+        // Named after the factor actually used, which may be reduced by `min_points`.
+        let out_file = get_output_file_path(
+            &in_file,
+            Action::Decimate(effective_factor_m),
+            write_options,
+        );
+        let original_point_count = count_points(&gpx);
+
+        let decimate = transform::Decimate {
+            factor_m: effective_factor_m,
+            offset,
+            max_points_per_segment,
+        };
+
+        if verbose && max_points_per_segment.is_some() {
+            for (t, track) in gpx.tracks.iter().enumerate() {
+                for (s, segment) in track.segments.iter().enumerate() {
+                    println!(
+                        "'{}': track #{t} / segment #{s}: decimated by {}",
+                        in_file.as_ref().display(),
+                        decimate.segment_factor(segment)
+                    );
+                }
+            }
+        }
+
+        decimate.apply(&mut gpx)?;
+
+        if let Some(fraction) = random_fraction {
+            transform::RandomSample { fraction, seed }.apply(&mut gpx)?;
+            println!(
+                "'{}': {} point(s) kept by the random sampling",
+                in_file.as_ref().display(),
+                count_points(&gpx)
+            );
+        }
+
+        if let Some(max_distance_m) = despike_m {
+            let point_count = count_points(&gpx);
+            transform::Despike { max_distance_m }.apply(&mut gpx)?;
+            println!(
+                "'{}': {} spike(s) removed",
+                in_file.as_ref().display(),
+                point_count - count_points(&gpx)
+            );
+        }
+
+        if verbose {
+            let point_count = count_points(&gpx);
+            println!(
+                "'{}': {original_point_count} -> {point_count} points, {}",
+                in_file.as_ref().display(),
+                format_reduction(original_point_count, point_count)
+            );
+            summary.push((
+                in_file.as_ref().to_path_buf(),
+                original_point_count,
+                point_count,
+            ));
+        }
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    if !affected_files.is_empty() {
+        println!(
+            "The factor was reduced for {} file(s) to keep at least {} points:",
+            affected_files.len(),
+            min_points.expect("Files can only be affected with a minimum number of points")
+        );
+        for file in affected_files {
+            println!("- {}", file.display());
+        }
+    }
+
+    if verbose {
+        print_decimation_summary(&summary);
+    }
+
+    Ok(())
+}
+
+/// Remove the tracks left without points by a transformation (see `transform::PruneEmptyTracks`),
+/// and report how many were removed.
+fn prune_empty_tracks(gpx: &mut gpx::Gpx, file: &impl AsRef<Path>) -> eyre::Result<()> {
+    let track_count = gpx.tracks.len();
+    transform::PruneEmptyTracks.apply(gpx)?;
+    let removed_count = track_count - gpx.tracks.len();
+    if removed_count > 0 {
+        println!(
+            "'{}': {removed_count} track(s) without points removed",
+            file.as_ref().display()
+        );
+    }
+    Ok(())
+}
+
+/// Options for simplifying files, besides the algorithm.
+#[derive(Clone, Debug, Default)]
+pub struct SimplifyOptions {
+    /// The maximum distance of the removed points from the simplified track (see `transform::SimplifyRdp`).
+    pub tolerance_m: Option<f64>,
+    /// The minimum area of the triangles of the kept points (see `transform::SimplifyVisvalingam`).
+    pub min_area_m2: Option<f64>,
+    /// The maximum number of points of each segment (see `transform::SimplifyVisvalingam`).
+    pub max_points: Option<usize>,
+    /// Skip the files without tracks instead of writing them unchanged.
+    pub skip_empty: bool,
+}
+
+/// Simplify the tracks of each file, with one of the algorithms (see `SimplifyAlgorithm`):
+/// - `Rdp` needs `options.tolerance_m`;
+/// - `Visvalingam` needs `options.min_area_m2` and/or `options.max_points`.
+pub fn simplify(
+    files: &[impl AsRef<Path>],
+    algorithm: SimplifyAlgorithm,
+    options: &SimplifyOptions,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    let SimplifyOptions {
+        tolerance_m,
+        min_area_m2,
+        max_points,
+        skip_empty,
+    } = *options;
+    let transform: Box<dyn Transform> = match algorithm {
+        SimplifyAlgorithm::Rdp => {
+            if min_area_m2.is_some() || max_points.is_some() {
+                return Err(eyre!(
+                    "A minimum area or a maximum number of points is only used by the 'visvalingam' algorithm"
+                ));
+            }
+            let tolerance_m =
+                tolerance_m.ok_or_else(|| eyre!("The 'rdp' algorithm needs a tolerance"))?;
+            Box::new(transform::SimplifyRdp { tolerance_m })
+        }
+        SimplifyAlgorithm::Visvalingam => {
+            if tolerance_m.is_some() {
+                return Err(eyre!("A tolerance is only used by the 'rdp' algorithm"));
+            }
+            if min_area_m2.is_none() && max_points.is_none() {
+                return Err(eyre!(
+                    "The 'visvalingam' algorithm needs a minimum area and/or a maximum number of points"
+                ));
+            }
+            Box::new(transform::SimplifyVisvalingam {
+                min_area_m2,
+                max_points,
+            })
+        }
+    };
+
+    check_files(files)?;
+
+    for file in files {
+        let mut gpx = load_gpx(file, read_options)?;
+        if warn_if_no_tracks(&gpx, file, "simplify") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+        let out_file = get_output_file_path(file, Action::Simplify, write_options);
+
+        let tracks_extensions = load_track_extensions(file)?;
+
+        let point_count = count_points(&gpx);
+        transform.apply(&mut gpx)?;
+        println!(
+            "'{}': {point_count} -> {} points, {}",
+            file.as_ref().display(),
+            count_points(&gpx),
+            format_reduction(point_count, count_points(&gpx))
+        );
+
+        if let Some(original) = &original {
+            preview_changes(file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Apply a pipeline of transformations to each file.
+/// With `prune`, the tracks left without points (e.g. after a crop) are removed afterwards.
+pub fn pipeline(
+    files: &[impl AsRef<Path>],
+    pipeline: &str,
+    prune: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    // Before anything else, so that a typo does not leave some files processed and others not.
+    let transforms = transform::parse_pipeline(pipeline)?;
+    check_files(files)?;
+
+    for file in files {
+        let mut gpx = load_gpx(file, read_options)?;
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        transforms.apply(&mut gpx)?;
+
+        if prune {
+            prune_empty_tracks(&mut gpx, file)?;
+        }
+
+        let out_file = get_output_file_path(file, Action::Pipeline, write_options);
+        if let Some(original) = &original {
+            preview_changes(file, &out_file, original, &gpx);
+        }
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+pub fn gap_fill(
+    files: &[impl AsRef<Path>],
+    max_gap_m: f64,
+    step_m: f64,
+    limit_m: Option<f64>,
+    skip_empty: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::GapFill, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        if warn_if_no_tracks(&gpx, &in_file, "fill the gaps of") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        let tracks_extensions = load_track_extensions(&in_file)?;
+
+        let point_count = count_points(&gpx);
+        transform::GapFill {
+            max_gap_m,
+            step_m,
+            limit_m,
+        }
+        .apply(&mut gpx)?;
+        println!(
+            "'{}': {} point(s) added",
+            in_file.as_ref().display(),
+            count_points(&gpx) - point_count
+        );
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+pub fn route_to_track(
+    files: &[impl AsRef<Path>],
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::RouteToTrack, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        if gpx.routes.is_empty() {
+            println!("No routes in '{}'", in_file.as_ref().display());
+        }
+
+        transform::RouteToTrack.apply(&mut gpx)?;
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Make a track of each segment of each track of each file (see `transform::SegmentsToTracks`).
+/// The new tracks keep the extensions (e.g. the color) of their track.
+pub fn segments_to_tracks(
+    files: &[impl AsRef<Path>],
+    skip_empty: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::SegmentsToTracks, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        if warn_if_no_tracks(&gpx, &in_file, "explode") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        let track_counts = transform::SegmentsToTracks::track_counts(&gpx);
+        let tracks_extensions = zip(load_track_extensions(&in_file)?, &track_counts)
+            .flat_map(|(extensions, &count)| std::iter::repeat_n(extensions, count))
+            .collect::<Vec<_>>();
+
+        let track_count = gpx.tracks.len();
+        transform::SegmentsToTracks.apply(&mut gpx)?;
+        println!(
+            "'{}': {track_count} track(s) -> {} track(s)",
+            in_file.as_ref().display(),
+            gpx.tracks.len()
+        );
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Convert the tracks of each file into routes, after decimating them by `factor_m` if set.
+pub fn track_to_route(
+    files: &[impl AsRef<Path>],
+    factor_m: Option<u16>,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::TrackToRoute, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        if warn_if_no_tracks(&gpx, &in_file, "convert") {
+            continue;
+        }
+
+        if let Some(factor_m) = factor_m {
+            transform::Decimate {
+                factor_m,
+                offset: 0,
+                max_points_per_segment: None,
+            }
+            .apply(&mut gpx)?;
+        }
+        transform::TrackToRoute.apply(&mut gpx)?;
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// How to name the waypoints created from the points of a track.
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum WaypointNaming {
+    /// The index of the waypoint in the file, starting from 1.
+    Index,
+    /// The distance from the start of the track, in kilometers.
+    Distance,
+}
+
+/// Convert the points of a track to waypoints, keeping only every `every`-th point of each segment.
+fn track_to_waypoints(
+    track: &gpx::Track,
+    every: u16,
+    naming: WaypointNaming,
+    waypoints: &mut Vec<gpx::Waypoint>,
+) {
+    let mut track_distance = 0.0;
+
+    for segment in &track.segments {
+        let len = segment.points.len();
+
+        for (i, point) in segment.points.iter().enumerate() {
+            if i > 0 {
+                track_distance += distance(&segment.points[i - 1], point);
+            }
+
+            if !is_kept_by_decimation(i, len, every, 0) {
+                continue;
+            }
+
+            let mut waypoint = point.clone();
+            waypoint.name = Some(match naming {
+                WaypointNaming::Index => (waypoints.len() + 1).to_string(),
+                WaypointNaming::Distance => format!("{:.1} km", track_distance / 1000.0),
+            });
+            waypoints.push(waypoint);
+        }
+    }
+}
+
+pub fn to_waypoints(
+    files: &[impl AsRef<Path>],
+    every: u16,
+    naming: WaypointNaming,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    if every == 0 {
+        return Err(eyre!("Cannot keep every 0-th point"));
+    }
+
+    for file in files {
+        let gpx = load_gpx(file, read_options)?;
+
+        if warn_if_no_tracks(&gpx, file, "flatten") {
+            continue;
+        }
+
+        let mut waypoints = Vec::new();
+        for track in &gpx.tracks {
+            track_to_waypoints(track, every, naming, &mut waypoints);
+        }
+
+        println!("{} waypoints created", waypoints.len());
+
+        let waypoints_gpx = gpx::Gpx {
+            version: gpx::GpxVersion::Gpx11,
+            creator: Some(get_creator()),
+            metadata: gpx.metadata,
+            waypoints,
+            ..Default::default()
+        };
+
+        let out_file = get_output_file_path(file, Action::ToWaypoints, write_options);
+        save_gpx(&waypoints_gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+pub fn from_geojson(
+    files: &[impl AsRef<Path>],
+    name: Option<&OutputName>,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files_of_format(files, "GeoJSON", &["geojson", "json"])?;
+
+    for file in files {
+        let file = file.as_ref();
+        println!("Loading GeoJSON from '{}'...", file.display());
+
+        let reader = BufReader::new(File::open(file)?);
+        let document = serde_json::from_reader(reader)
+            .map_err(|err| eyre!("Cannot parse '{}': {err}", file.display()))?;
+
+        let mut gpx = geojson::feature_collection_to_gpx(&document)
+            .map_err(|err| eyre!("Cannot convert '{}': {err}", file.display()))?;
+        gpx.creator = Some(get_creator());
+
+        // Not just the same name with another extension,
+        // so that converting back a file exported with `to_geojson()` does not overwrite the original GPX file.
+        let out_file =
+            get_output_file_path(&file, Action::FromGeojson, write_options).with_extension("gpx");
+        if let Some(name) = name {
+            set_name(&mut gpx, name, &out_file);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+pub fn anonymize(
+    files: &[impl AsRef<Path>],
+    keep_elevation: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::Anonymize, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        transform::Anonymize { keep_elevation }.apply(&mut gpx)?;
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Get the index of the longest item, the first one in case of ties.
+fn index_of_longest<T>(items: &[T], length: impl Fn(&T) -> f64) -> Option<usize> {
+    items
+        .iter()
+        .map(length)
+        .enumerate()
+        .fold(
+            None,
+            |longest: Option<(usize, f64)>, (i, length)| match longest {
+                Some((_, max)) if length <= max => longest,
+                _ => Some((i, length)),
+            },
+        )
+        .map(|(i, _)| i)
+}
+
+/// Only keep the longest track of each file, to clean up the stray fragments created by some devices.
+/// With `by_segment`, only the longest segment (in its track) is kept instead.
+/// The length is the number of points, or the distance with `by_distance`.
+/// The routes and the waypoints are kept.
+pub fn keep_longest(
+    files: &[impl AsRef<Path>],
+    by_segment: bool,
+    by_distance: bool,
+    skip_empty: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::KeepLongest, write_options))
+        .collect::<Vec<_>>();
+
+    let segment_length = |segment: &gpx::TrackSegment| {
+        if by_distance {
+            compute_segment_distance(segment)
+        } else {
+            segment.points.len() as f64
+        }
+    };
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        if warn_if_no_tracks(&gpx, &in_file, "keep") && skip_empty {
+            continue;
+        }
+
+        let original = write_options.dry_run.then(|| gpx.clone());
+        let mut tracks_extensions = load_track_extensions(&in_file)?;
+
+        let segments = gpx
+            .tracks
+            .iter()
+            .enumerate()
+            .flat_map(|(t, track)| (0..track.segments.len()).map(move |s| (t, s)))
+            .collect::<Vec<_>>();
+
+        let (t, s) = if by_segment {
+            match index_of_longest(&segments, |&(t, s)| {
+                segment_length(&gpx.tracks[t].segments[s])
+            }) {
+                Some(i) => (segments[i].0, Some(segments[i].1)),
+                None => (0, None),
+            }
+        } else {
+            let t = index_of_longest(&gpx.tracks, |track| {
+                track.segments.iter().map(segment_length).sum()
+            });
+            (t.unwrap_or_default(), None)
+        };
+
+        if !gpx.tracks.is_empty() {
+            let dropped = match s {
+                Some(s) => format!(
+                    "kept segment #{s} of track #{t}, dropped {} other segment(s)",
+                    segments.len() - 1
+                ),
+                None => format!(
+                    "kept track #{t}, dropped {} other track(s)",
+                    gpx.tracks.len() - 1
+                ),
+            };
+            println!("'{}': {dropped}", in_file.as_ref().display());
+
+            let mut track = gpx.tracks.swap_remove(t);
+            if let Some(s) = s {
+                track.segments = vec![track.segments.swap_remove(s)];
+            }
+
+            gpx.tracks = vec![track];
+            gpx.creator = Some(get_creator());
+            tracks_extensions = vec![tracks_extensions.get(t).cloned().flatten()];
+        }
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx_with_track_extensions(&gpx, &tracks_extensions, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the points of each file that are farther than `tolerance_m` from the tracks and routes
+/// of the `reference` file (see `transform::NearRoute`).
+/// With `prune`, the tracks left without points are removed afterwards.
+pub fn near_route(
+    files: &[impl AsRef<Path>],
+    reference: &impl AsRef<Path>,
+    tolerance_m: f64,
+    prune: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+    check_files(&[reference])?;
+
+    let reference = load_gpx(reference, read_options)?;
+    if count_points(&reference) == 0 && reference.routes.iter().all(|route| route.points.is_empty())
+    {
+        return Err(eyre!("The reference has no track points or route points"));
+    }
+
+    let near_route = transform::NearRoute {
+        reference,
+        tolerance_m,
+    };
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::NearRoute, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        let before = count_points(&gpx);
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        near_route.apply(&mut gpx)?;
+
+        println!(
+            "'{}': kept {} of {before} track points within {tolerance_m} m of the reference",
+            in_file.as_ref().display(),
+            count_points(&gpx)
+        );
+        if prune {
+            prune_empty_tracks(&mut gpx, &in_file)?;
+        }
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Extract the track points of each file in a range (see `transform::Extract`) into a new file.
+/// It fails if a file has no points in the range.
+/// With `prune`, the tracks left without points are removed afterwards.
+pub fn extract(
+    files: &[impl AsRef<Path>],
+    range: transform::PointRange,
+    prune: bool,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let output_files = files
+        .iter()
+        .map(|f| get_output_file_path(f, Action::Extract, write_options))
+        .collect::<Vec<_>>();
+
+    for (in_file, out_file) in zip(files, output_files) {
+        let mut gpx = load_gpx(&in_file, read_options)?;
+        let before = count_points(&gpx);
+        let original = write_options.dry_run.then(|| gpx.clone());
+
+        transform::Extract { range }
+            .apply(&mut gpx)
+            .map_err(|err| {
+                eyre!(
+                    "Cannot extract from '{}': {err}",
+                    in_file.as_ref().display()
+                )
+            })?;
+
+        let after = count_points(&gpx);
+        if after == 0 {
+            return Err(eyre!(
+                "No track points of '{}' are in the range to extract",
+                in_file.as_ref().display()
+            ));
+        }
+        println!(
+            "'{}': extracted {after} of {before} track points",
+            in_file.as_ref().display()
+        );
+        if prune {
+            prune_empty_tracks(&mut gpx, &in_file)?;
+        }
+
+        if let Some(original) = &original {
+            preview_changes(&in_file, &out_file, original, &gpx);
+        }
+
+        save_gpx(&gpx, &out_file, write_options)?;
+    }
+
+    Ok(())
+}
+
+/// Report the points with invalid coordinates of each file.
+/// With `strict`, it fails if any file has such points.
+pub fn validate(files: &[impl AsRef<Path>], strict: bool) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let mut invalid_files = Vec::new();
+
+    for file in files {
+        let gpx = read_gpx_file(file, &ReadOptions::default())?;
+        let invalid_points = find_invalid_points(&gpx);
+
+        if invalid_points.is_empty() {
+            println!(
+                "'{}': all the coordinates are valid",
+                file.as_ref().display()
+            );
+            continue;
+        }
+
+        println!(
+            "'{}': {} point(s) with invalid coordinates",
+            file.as_ref().display(),
+            invalid_points.len()
+        );
+        for point in invalid_points {
+            println!("- {point}");
+        }
+
+        invalid_files.push(file.as_ref());
+    }
+
+    if strict && !invalid_files.is_empty() {
+        return Err(eyre!(
+            "{} file(s) have points with invalid coordinates",
+            invalid_files.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Print the center of each file.
+/// With `json`, a single JSON array with the centers of all the files is printed at the end, in this format.
+pub fn center(
+    files: &[impl AsRef<Path>],
+    weighted: bool,
+    json: Option<JsonFormat>,
+    read_options: &ReadOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let mut results = Vec::new();
+
+    for file in files {
+        let gpx = read_gpx_file(file, read_options)?;
+
+        let lines = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| segment.points.as_slice())
+            .chain(gpx.routes.iter().map(|route| route.points.as_slice()));
+
+        let center = compute_center(lines, weighted);
+
+        if json.is_some() {
+            results.push(serde_json::json!({
+                "file": file.as_ref().to_string_lossy(),
+                "lat": center.map(|(lat, _)| lat),
+                "lon": center.map(|(_, lon)| lon),
+            }));
+        } else {
+            match center {
+                Some((lat, lon)) => println!(
+                    "Center of '{}' = {lat:.6}, {lon:.6}",
+                    file.as_ref().display()
+                ),
+                None => println!("Center of '{}' = n/a", file.as_ref().display()),
             }
         }
+    }
 
-        gpx.creator = Some(get_creator());
+    if let Some(format) = json {
+        print_json(&serde_json::Value::Array(results), format)?;
+    }
+
+    Ok(())
+}
+
+pub fn to_geojson(
+    files: &[impl AsRef<Path>],
+    trackpoints_only: bool,
+    order: AxisOrder,
+    name: Option<&OutputName>,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    for file in files {
+        let mut gpx = load_gpx(file, read_options)?;
+
+        let out_file = get_export_file_path(
+            file,
+            OutputFormat::Geojson,
+            Action::ToGeojson,
+            write_options,
+        );
+        if let Some(name) = name {
+            set_name(&mut gpx, name, &out_file);
+        }
+
+        if write_options.dry_run {
+            println!("Dry run: not saving GeoJSON to '{}'", out_file.display());
+            continue;
+        }
+        save_geojson(&gpx, &out_file, trackpoints_only, order)?;
+    }
 
-        save_gpx(&gpx, &out_file)?;
+    Ok(())
+}
+
+/// Convert each file to KML.
+/// With `color_by`, the lines of the tracks are colored in `bands` bands of the metric.
+pub fn to_kml(
+    files: &[impl AsRef<Path>],
+    color_by: Option<ColorBy>,
+    bands: usize,
+    name: Option<&OutputName>,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    let coloring = color_by.map(|by| kml::Coloring { by, bands });
+
+    for file in files {
+        let mut gpx = load_gpx(file, read_options)?;
+
+        let out_file = get_export_file_path(file, OutputFormat::Kml, Action::ToKml, write_options);
+        if let Some(name) = name {
+            set_name(&mut gpx, name, &out_file);
+        }
+
+        if write_options.dry_run {
+            println!("Dry run: not saving KML to '{}'", out_file.display());
+            continue;
+        }
+        save_kml(&gpx, &out_file, coloring.as_ref())?;
+    }
+
+    Ok(())
+}
+
+pub fn to_csv(
+    files: &[impl AsRef<Path>],
+    trackpoints_only: bool,
+    order: AxisOrder,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    for file in files {
+        let gpx = load_gpx(file, read_options)?;
+
+        let out_file = get_export_file_path(file, OutputFormat::Csv, Action::ToCsv, write_options);
+        if write_options.dry_run {
+            println!("Dry run: not saving CSV to '{}'", out_file.display());
+            continue;
+        }
+        save_csv(&gpx, &out_file, trackpoints_only, order)?;
+    }
+
+    Ok(())
+}
+
+/// Export the elevation profile of each file as plain text (see `profile::write_profile()`),
+/// with the distances in `distance_unit` and the elevations in `elevation_unit`,
+/// to `<stem>-profile.txt` next to the file.
+pub fn to_profile(
+    files: &[impl AsRef<Path>],
+    distance_unit: DistanceUnit,
+    elevation_unit: ElevationUnit,
+    read_options: &ReadOptions,
+    write_options: &WriteOptions,
+) -> eyre::Result<()> {
+    check_files(files)?;
+
+    for file in files {
+        let gpx = load_gpx(file, read_options)?;
+        warn_if_no_tracks(&gpx, file, "export");
+
+        let out_file =
+            get_output_file_path(file, Action::ToProfile, write_options).with_extension("txt");
+        if write_options.dry_run {
+            println!("Dry run: not saving profile to '{}'", out_file.display());
+            continue;
+        }
+        save_profile(&gpx, &out_file, distance_unit, elevation_unit)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A directory for the files of a test, removed at the end of the test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("merge-my-gpx-test-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Get the path of a file of `tests/fixtures`.
+    pub(crate) fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    pub(crate) fn make_point(lat: f64, lon: f64) -> gpx::Waypoint {
+        gpx::Waypoint::new((lon, lat).into())
+    }
+
+    /// Make a track with a segment per list of coordinates (latitude, longitude).
+    pub(crate) fn make_track(name: &str, segments: &[&[(f64, f64)]]) -> gpx::Track {
+        let mut track = gpx::Track::new();
+        track.name = Some(name.to_owned());
+        track.segments = segments
+            .iter()
+            .map(|points| {
+                let mut segment = gpx::TrackSegment::new();
+                segment.points = points
+                    .iter()
+                    .map(|&(lat, lon)| make_point(lat, lon))
+                    .collect();
+                segment
+            })
+            .collect();
+        track
+    }
+
+    pub(crate) fn make_gpx(tracks: Vec<gpx::Track>) -> gpx::Gpx {
+        gpx::Gpx {
+            version: gpx::GpxVersion::Gpx11,
+            creator: Some(get_creator()),
+            tracks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decimated_files_are_named_after_the_reduced_factor() {
+        let dir = TempDir::new("decimate-min-points");
+        let file = dir.path().join("ride.gpx");
+        let points = (0..10)
+            .map(|i| (45.0, 5.0 + f64::from(i) * 0.01))
+            .collect::<Vec<_>>();
+        let gpx = make_gpx(vec![make_track("ride", &[&points])]);
+        save_gpx(&gpx, &file, &WriteOptions::default()).unwrap();
+        let options = DecimateOptions {
+            min_points: Some(5),
+            ..Default::default()
+        };
+
+        decimate(
+            &[&file],
+            5,
+            &options,
+            false,
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        // Decimating 10 points by 5 would only keep 3 of them, by 2 it keeps 6.
+        assert!(dir.path().join("ride-decimated-by-2.gpx").is_file());
+        assert!(!dir.path().join("ride-decimated-by-5.gpx").exists());
+    }
+
+    /// Make a segment with a point per time, in seconds after 2024-06-01 08:00:00 UTC.
+    fn make_timed_segment(seconds: &[i64]) -> gpx::TrackSegment {
+        let start = OffsetDateTime::from_unix_timestamp(1_717_228_800).unwrap();
+        let mut segment = gpx::TrackSegment::new();
+        segment.points = seconds
+            .iter()
+            .map(|&s| {
+                let mut point = make_point(45.0, 5.0);
+                point.time = Some((start + time::Duration::seconds(s)).into());
+                point
+            })
+            .collect();
+        segment
+    }
+
+    #[test]
+    fn join_segments_by_gap_keeps_the_segments_of_the_files() {
+        let files_segments = vec![
+            vec![
+                make_timed_segment(&[0, 10]),
+                make_timed_segment(&[600, 610]),
+            ],
+            // 20 seconds after the end of the first file.
+            vec![make_timed_segment(&[630, 640]), make_timed_segment(&[1200])],
+            // An hour after the end of the second file.
+            vec![make_timed_segment(&[4800])],
+            // 10 seconds before the end of the third file.
+            vec![make_timed_segment(&[4790, 4795])],
+        ];
+
+        let segments = join_segments_by_gap(files_segments, std::time::Duration::from_secs(60));
+
+        let lengths = segments
+            .iter()
+            .map(|segment| segment.points.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, [2, 4, 1, 1, 2]);
+    }
+
+    #[test]
+    fn overlap_finds_the_close_points_near_the_poles() {
+        // At 88 degrees north, 0.001 degree of longitude is about 4 meters, so the points are 2 cells apart.
+        let points = [make_point(88.0, 10.0005)];
+        let others = [make_point(88.0, 10.0025)];
+        assert!(distance(&points[0], &others[0]) < DUPLICATE_TOLERANCE_M);
+
+        let overlap = compute_overlap(
+            &points.iter().collect::<Vec<_>>(),
+            &others.iter().collect::<Vec<_>>(),
+        );
+        assert_eq!(overlap, 1.0);
+    }
+
+    #[test]
+    fn distances_across_the_antimeridian_are_short() {
+        // 2 degrees of longitude: about 222 km at the equator, half of it at 60 degrees.
+        let across = distance(&make_point(0.0, 179.0), &make_point(0.0, -179.0));
+        assert!((across - 222_390.0).abs() < 1000.0, "{across}");
+        let across = distance(&make_point(60.0, 179.0), &make_point(60.0, -179.0));
+        assert!((across - 111_195.0).abs() < 1000.0, "{across}");
+
+        let along = distance(&make_point(60.0, -1.0), &make_point(60.0, 1.0));
+        assert!((across - along).abs() < 1e-6);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_names_keep_the_bytes_of_non_utf8_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // "ride-\xE9t\xE9.gpx": "été" in Latin-1, which is not valid UTF-8.
+        let file = Path::new("rides").join(OsStr::from_bytes(b"ride-\xE9t\xE9.gpx"));
+        let expected = Path::new("rides").join(OsStr::from_bytes(b"ride-\xE9t\xE9-inverted.gpx"));
+
+        assert_eq!(add_suffix_to_file_stem(&file, "inverted"), expected);
+        assert_eq!(
+            get_output_file_path(&file, Action::Invert, &WriteOptions::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn merge_with_keep_tracks_keeps_the_details_of_the_tracks() {
+        let dir = TempDir::new("merge-keep-tracks");
+        let output_file = dir.path().join("merged.gpx");
+        let mut track = make_track("Morning", &[&[(45.0, 5.0), (45.1, 5.1)]]);
+        track.comment = Some("Windy".to_owned());
+        track.description = Some("To the lake".to_owned());
+        track.type_ = Some("cycling".to_owned());
+        track.links.push(gpx::Link {
+            href: "https://example.com/morning".to_owned(),
+            text: Some("Photos".to_owned()),
+            ..Default::default()
+        });
+        let files_gpxs = vec![
+            (PathBuf::from("a.gpx"), make_gpx(vec![track.clone()])),
+            (
+                PathBuf::from("b.gpx"),
+                make_gpx(vec![make_track("Evening", &[&[(46.0, 6.0)]])]),
+            ),
+        ];
+        let options = MergeOptions {
+            keep_tracks: true,
+            ..Default::default()
+        };
+
+        merge_loaded(
+            files_gpxs,
+            &output_file,
+            &options,
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let merged = read_gpx_file(&output_file, &ReadOptions::default()).unwrap();
+        assert_eq!(merged.tracks.len(), 2);
+        let written = &merged.tracks[0];
+        assert_eq!(written.name, track.name);
+        assert_eq!(written.comment, track.comment);
+        assert_eq!(written.description, track.description);
+        assert_eq!(written.type_, track.type_);
+        assert_eq!(written.links.len(), 1);
+        assert_eq!(written.links[0].href, track.links[0].href);
+        assert_eq!(written.links[0].text, track.links[0].text);
+        assert_eq!(merged.tracks[1].name.as_deref(), Some("Evening"));
+    }
+
+    #[test]
+    fn tracks_cropped_away_are_pruned() {
+        let dir = TempDir::new("prune-cropped");
+        let file = dir.path().join("ride.gpx");
+        let gpx = make_gpx(vec![
+            make_track("inside", &[&[(45.5, 5.5), (45.6, 5.6)]]),
+            make_track("outside", &[&[(47.0, 7.0), (47.1, 7.1)]]),
+        ]);
+        save_gpx(&gpx, &file, &WriteOptions::default()).unwrap();
+        let options = WriteOptions::default();
+        let out_file = get_output_file_path(&file, Action::Pipeline, &options);
+        let read_track_names = || {
+            read_gpx_file(&out_file, &ReadOptions::default())
+                .unwrap()
+                .tracks
+                .into_iter()
+                .map(|track| track.name.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        pipeline(
+            &[&file],
+            "crop:bbox=45,5,46,6",
+            true,
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(read_track_names(), ["inside"]);
+
+        pipeline(
+            &[&file],
+            "crop:bbox=45,5,46,6",
+            false,
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(read_track_names(), ["inside", "outside"]);
+    }
+
+    fn write_to_string(gpx: &gpx::Gpx, options: &WriteOptions) -> String {
+        let mut buffer = Vec::new();
+        write_gpx_with_options(gpx, &mut buffer, options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Make GPX data with a track and a waypoint, whose points have elevations and times.
+    fn make_gpx_with_elevations_and_times() -> gpx::Gpx {
+        let mut gpx = make_gpx(vec![make_track("Ride", &[&[(45.0, 5.0), (45.1, 5.1)]])]);
+        gpx.waypoints.push(make_point(45.05, 5.05));
+        for_each_point_mut(&mut gpx, |point| {
+            point.elevation = Some(500.0);
+            point.time = Some(
+                OffsetDateTime::from_unix_timestamp(1_717_228_800)
+                    .unwrap()
+                    .into(),
+            );
+        });
+        gpx
+    }
+
+    #[test]
+    fn strip_elevation_and_strip_time_remove_their_elements() {
+        let gpx = make_gpx_with_elevations_and_times();
+        let written = write_to_string(&gpx, &WriteOptions::default());
+        assert!(
+            written.contains("<ele>") && written.contains("<time>"),
+            "{written}"
+        );
+
+        let options = WriteOptions {
+            strip_elevation: true,
+            ..Default::default()
+        };
+        let written = write_to_string(&gpx, &options);
+        assert!(
+            !written.contains("<ele>") && written.contains("<time>"),
+            "{written}"
+        );
+
+        let options = WriteOptions {
+            strip_time: true,
+            ..Default::default()
+        };
+        let written = write_to_string(&gpx, &options);
+        assert!(
+            written.contains("<ele>") && !written.contains("<time>"),
+            "{written}"
+        );
+    }
+
+    #[test]
+    fn ascii_files_have_no_byte_order_mark() {
+        let gpx = make_gpx(vec![make_track("Été", &[&[(45.0, 5.0)]])]);
+        let options = WriteOptions {
+            with_bom: true,
+            ascii: true,
+            ..Default::default()
+        };
+
+        let written = write_to_string(&gpx, &options);
+        assert!(written.is_ascii(), "{written}");
+        assert!(written.contains("&#201;t&#233;"), "{written}");
+    }
+
+    /// Save a file with a track of one segment, whose first point is at `lat` to recognize it.
+    fn save_file_at(dir: &Path, name: &str, lat: f64) -> PathBuf {
+        let file = dir.join(name);
+        let gpx = make_gpx(vec![make_track(name, &[&[(lat, 5.0), (lat, 5.1)]])]);
+        save_gpx(&gpx, &file, &WriteOptions::default()).unwrap();
+        file
+    }
+
+    #[test]
+    fn exports_write_nothing_in_a_dry_run() {
+        let dir = TempDir::new("export-dry-run");
+        let file = save_file_at(dir.path(), "ride.gpx", 45.0);
+        let options = WriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        to_geojson(
+            &[&file],
+            false,
+            AxisOrder::LonLat,
+            None,
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+        to_kml(&[&file], None, 5, None, &ReadOptions::default(), &options).unwrap();
+        to_csv(
+            &[&file],
+            false,
+            AxisOrder::LatLon,
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+        to_profile(
+            &[&file],
+            DistanceUnit::Km,
+            ElevationUnit::M,
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+
+        let entries = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn dropped_extensions_of_track_points_are_warned_about() {
+        let file = fixture("garmin.gpx");
+        let warning = describe_dropped_extensions(&file, File::open(&file).unwrap()).unwrap();
+
+        let warning = warning.expect("The extensions of the track points are lost");
+        assert!(warning.contains("3 <trkpt>"), "{warning}");
+        assert!(!warning.contains("<trk>"), "{warning}");
+    }
+
+    #[test]
+    fn kept_extensions_are_not_warned_about() {
+        let gpx = make_gpx(vec![make_track("Ride", &[&[(45.0, 5.0)]])]);
+        let mut buffer = Vec::new();
+        write_gpx(&gpx, &mut buffer).unwrap();
+
+        let warning = describe_dropped_extensions(&"ride.gpx", buffer.as_slice()).unwrap();
+        assert_eq!(warning, None);
+    }
+
+    #[test]
+    fn only_strict_reads_fail_on_broken_files() {
+        let dir = TempDir::new("strict-read");
+        let file = dir.path().join("empty.gpx");
+        save_gpx(&make_gpx(vec![]), &file, &WriteOptions::default()).unwrap();
+        let strict = ReadOptions { strict: true };
+
+        let err = read_gpx_file(&file, &strict).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("has no tracks, routes or waypoints")
+        );
+        assert!(read_gpx_file(&file, &ReadOptions::default()).is_ok());
+        // The checks of "validate" are not affected by the strict reads of the other commands.
+        assert!(validate(&[&file], false).is_ok());
+    }
+
+    #[test]
+    fn files_with_an_uppercase_extension_are_processed() {
+        let dir = TempDir::new("uppercase-extension");
+        let file = save_file_at(dir.path(), "RIDE.GPX", 45.0);
+
+        let listed =
+            list_gpx_files(&dir.path(), &FileFilter::default(), FileSort::default()).unwrap();
+        assert_eq!(listed, std::slice::from_ref(&file));
+
+        load_gpx(&file, &ReadOptions::default()).unwrap();
+        invert(
+            &[&file],
+            &InvertOptions::default(),
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        assert!(dir.path().join("RIDE-inverted.GPX").is_file());
+    }
+
+    #[test]
+    fn input_lists_are_relative_to_their_directory() {
+        let dir = TempDir::new("input-list");
+        let list = dir.path().join("list.txt");
+        std::fs::write(
+            &list,
+            "# The rides\n a.gpx \n\nsub/b.gpx\n/abs/c.gpx\nhttps://example.com/d.gpx\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_input_list(&list).unwrap(),
+            [
+                dir.path().join("a.gpx"),
+                dir.path().join("sub/b.gpx"),
+                PathBuf::from("/abs/c.gpx"),
+                PathBuf::from("https://example.com/d.gpx"),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlap_with_lines_is_computed_within_the_tolerance() {
+        let track = make_track("Ride", &[&[(45.0, 5.0), (45.0, 5.01)]]);
+        let close = make_point(45.0002, 5.005); // About 22 meters away.
+        let far = make_point(45.001, 5.005); // About 111 meters away.
+        let segments = [&track.segments[0]];
+
+        assert_eq!(
+            compute_overlap_with_lines(&[&close, &far], &segments, 50.0),
+            0.5
+        );
+        assert_eq!(
+            compute_overlap_with_lines(&[&close, &far], &segments, 10.0),
+            0.0
+        );
+    }
+
+    /// Check that a point is only searched in a few cells of the grid sized for a tolerance
+    /// (with cells of `DUPLICATE_GRID_STEP_DEG`, a huge tolerance would need millions of them).
+    pub(crate) fn assert_few_cells_are_searched(tolerance_m: f64) {
+        let step_deg = get_grid_step(tolerance_m);
+        let count = get_grid_cells_near((45.0, 5.0), (45.0, 5.0), tolerance_m, step_deg).count();
+        assert!(
+            count <= 16,
+            "{count} cells are searched with {tolerance_m} m"
+        );
+    }
+
+    #[test]
+    fn overlap_with_lines_works_with_a_huge_tolerance() {
+        let points = [
+            (45.0, 5.0),
+            (45.1, 5.1),
+            (45.2, 5.2),
+            (45.3, 5.3),
+            (45.4, 5.4),
+        ];
+        let track = make_track("Ride", &[&points]);
+        let others = points.map(|(lat, lon)| make_point(lat + 1.0, lon));
+        let others = others.iter().collect::<Vec<_>>();
+
+        let overlap = compute_overlap_with_lines(&others, &[&track.segments[0]], 200_000.0);
+
+        assert_eq!(overlap, 1.0);
+        assert_few_cells_are_searched(200_000.0);
+    }
+
+    #[test]
+    fn overlap_finds_the_close_points_across_the_antimeridian() {
+        // About 9 meters apart, on each side of the antimeridian.
+        let east = gpx::TrackSegment {
+            points: vec![make_point(10.0, 179.99996)],
+        };
+        let west = gpx::TrackSegment {
+            points: vec![make_point(10.0, -179.99996)],
+        };
+        assert!(distance(&east.points[0], &west.points[0]) < DUPLICATE_TOLERANCE_M);
+
+        let east_points = east.points.iter().collect::<Vec<_>>();
+        let west_points = west.points.iter().collect::<Vec<_>>();
+        assert_eq!(compute_overlap(&east_points, &west_points), 1.0);
+        assert_eq!(compute_overlap(&west_points, &east_points), 1.0);
+        assert_eq!(
+            compute_overlap_with_lines(&east_points, &[&west], 50.0),
+            1.0
+        );
+        assert_eq!(
+            compute_overlap_with_lines(&west_points, &[&east], 50.0),
+            1.0
+        );
+    }
+
+    /// Get the latitudes of the first points of the segments of a file.
+    fn read_segment_latitudes(file: &Path) -> Vec<f64> {
+        read_gpx_file(&file, &ReadOptions::default())
+            .unwrap()
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| segment.points[0].point().y())
+            .collect()
+    }
+
+    #[test]
+    fn lines_are_only_indexed_in_the_cells_along_them() {
+        // A diagonal of 1 degree: its box has a million cells of 0.001 degree.
+        let diagonal = [make_point(45.0, 5.0), make_point(46.0, 6.0)];
+        let index = LineIndex::new([diagonal.as_slice()], 10.0);
+        assert!(index.grid.len() < 10_000, "{} cells", index.grid.len());
+        assert!(index.is_near(&make_point(45.5, 5.5)));
+        assert!(!index.is_near(&make_point(45.5, 5.6)));
+
+        // Across the antimeridian, the short way: 0.2 degree, not 359.8.
+        let across = [make_point(10.0, 179.9), make_point(10.0, -179.9)];
+        let index = LineIndex::new([across.as_slice()], 10.0);
+        assert!(index.grid.len() < 10_000, "{} cells", index.grid.len());
+        for lon in [179.95, 180.0, -179.95] {
+            assert!(index.is_near(&make_point(10.0, lon)), "{lon}");
+        }
+        assert!(!index.is_near(&make_point(10.0, 0.0)));
+    }
+
+    #[test]
+    fn merge_fails_cleanly_when_the_metadata_are_not_from_a_gpx_file() {
+        let dir = TempDir::new("merge-metadata-from");
+        let file = save_file_at(dir.path(), "ride.gpx", 45.0);
+        let metadata_file = dir.path().join("template.xml");
+        std::fs::write(&metadata_file, "<gpx/>").unwrap();
+        let options = MergeOptions {
+            metadata_from: Some(metadata_file),
+            ..Default::default()
+        };
+
+        let err = merge(
+            &[&file],
+            &dir.path().join("merged.gpx"),
+            &options,
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("template.xml"), "{err}");
+        assert!(!dir.path().join("merged.gpx").exists());
+    }
+
+    #[test]
+    fn merge_keeps_the_order_of_the_files() {
+        let dir = TempDir::new("merge-order");
+        let files = [("b.gpx", 2.0), ("c.gpx", 3.0), ("a.gpx", 1.0)]
+            .map(|(name, lat)| save_file_at(dir.path(), name, lat));
+        let output_file = dir.path().join("merged.gpx");
+
+        merge(
+            &files,
+            &output_file,
+            &MergeOptions::default(),
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(read_segment_latitudes(&output_file), [2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn merge_all_follows_the_order_of_list_gpx_files() {
+        let dir = TempDir::new("merge-all-order");
+        let input_dir = dir.path().join("rides");
+        std::fs::create_dir(&input_dir).unwrap();
+        for (name, lat) in [("b.gpx", 2.0), ("a.gpx", 1.0), ("C.gpx", 3.0)] {
+            save_file_at(&input_dir, name, lat);
+        }
+
+        let listed =
+            list_gpx_files(&input_dir, &FileFilter::default(), FileSort::default()).unwrap();
+        let names = listed
+            .iter()
+            .map(|file| file.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        // By the bytes of the names, so uppercase first.
+        assert_eq!(names, ["C.gpx", "a.gpx", "b.gpx"]);
+
+        merge_all(
+            &input_dir,
+            &FileFilter::default(),
+            true,
+            &MergeOptions::default(),
+            &ReadOptions::default(),
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let output_file = get_output_file_path(&input_dir, Action::Merge, &WriteOptions::default());
+        assert_eq!(read_segment_latitudes(&output_file), [3.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn merged_files_use_the_output_suffix() {
+        let dir = TempDir::new("merge-suffix");
+        for (name, lat) in [("2024-06-01-a.gpx", 1.0), ("2024-06-01-b.gpx", 2.0)] {
+            save_file_at(dir.path(), name, lat);
+        }
+        let options = WriteOptions {
+            output_suffix: Some("{action}-v2".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_merged_file_path(&dir.path(), &options),
+            dir.path().join("merged-v2.gpx")
+        );
+
+        group_merge(
+            &dir.path(),
+            &FileFilter::default(),
+            true,
+            r"^(\d{4}-\d{2}-\d{2})-",
+            &MergeOptions::default(),
+            &ReadOptions::default(),
+            &options,
+        )
+        .unwrap();
+
+        let output_file = dir.path().join("2024-06-01-merged-v2.gpx");
+        assert_eq!(read_segment_latitudes(&output_file), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn write_gpx_to_memory_can_be_read_back() {
+        let gpx = make_gpx(vec![make_track(
+            "Ride",
+            &[&[(45.0, 5.0), (45.1, 5.1)], &[(45.2, 5.2)]],
+        )]);
+
+        let mut buffer = Vec::new();
+        write_gpx(&gpx, &mut buffer).unwrap();
+        let written = read_gpx(buffer.as_slice()).unwrap();
+
+        assert_eq!(describe_shape(&written), describe_shape(&gpx));
+        assert_eq!(written.tracks[0].name.as_deref(), Some("Ride"));
+    }
+
+    #[test]
+    fn strip_waypoints_removes_the_waypoints() {
+        let gpx = make_gpx_with_elevations_and_times();
+        assert!(write_to_string(&gpx, &WriteOptions::default()).contains("<wpt"));
+
+        let options = WriteOptions {
+            strip_waypoints: true,
+            ..Default::default()
+        };
+        let written = write_to_string(&gpx, &options);
+        assert!(!written.contains("<wpt"), "{written}");
+        assert!(written.contains("<trkpt"), "{written}");
+    }
+
+    #[test]
+    fn save_gpx_writes_what_write_gpx_with_options_writes() {
+        let dir = TempDir::new("save-gpx");
+        let file = dir.path().join("ride.gpx");
+        let gpx = make_gpx(vec![make_track("Ride", &[&[(45.0, 5.0), (45.1, 5.1)]])]);
+        let options = WriteOptions {
+            compact: true,
+            strip_time: true,
+            ..Default::default()
+        };
+
+        save_gpx(&gpx, &file, &options).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            write_to_string(&gpx, &options)
+        );
+    }
+
+    #[test]
+    fn exports_use_the_output_suffix_and_timestamp() {
+        let dir = TempDir::new("export-suffix");
+        let file = save_file_at(dir.path(), "ride.gpx", 45.0);
+        let export = |options: &WriteOptions| {
+            let read_options = ReadOptions::default();
+            to_geojson(
+                &[&file],
+                false,
+                AxisOrder::LonLat,
+                None,
+                &read_options,
+                options,
+            )
+            .unwrap();
+            to_kml(&[&file], None, 5, None, &read_options, options).unwrap();
+            to_csv(&[&file], false, AxisOrder::LatLon, &read_options, options).unwrap();
+            to_profile(
+                &[&file],
+                DistanceUnit::Km,
+                ElevationUnit::M,
+                &read_options,
+                options,
+            )
+            .unwrap();
+        };
+
+        export(&WriteOptions::default());
+        for name in ["ride.geojson", "ride.kml", "ride.csv", "ride-profile.txt"] {
+            assert!(dir.path().join(name).is_file(), "{name}");
+        }
+
+        export(&WriteOptions {
+            output_suffix: Some("{action}-v2".to_owned()),
+            ..Default::default()
+        });
+        for name in [
+            "ride-geojson-v2.geojson",
+            "ride-kml-v2.kml",
+            "ride-csv-v2.csv",
+            "ride-profile-v2.txt",
+        ] {
+            assert!(dir.path().join(name).is_file(), "{name}");
+        }
+
+        export(&WriteOptions {
+            output_timestamp: Some(OffsetDateTime::from_unix_timestamp(1_717_228_800).unwrap()),
+            ..Default::default()
+        });
+        for name in [
+            "ride-geojson-20240601-080000.geojson",
+            "ride-kml-20240601-080000.kml",
+            "ride-csv-20240601-080000.csv",
+            "ride-profile-20240601-080000.txt",
+        ] {
+            assert!(dir.path().join(name).is_file(), "{name}");
+        }
+    }
+}
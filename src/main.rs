@@ -1,6 +1,19 @@
-use clap::{Parser, Subcommand};
-use merge_my_gpx::{decimate, info, invert, invert_all, merge, merge_all};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use merge_my_gpx::transform::{PointRange, TRANSFORM_NAMES};
+use merge_my_gpx::{
+    AxisOrder, ColorBy, DecimateOptions, DistanceUnit, ElevationUnit, FileFilter, FileOrder,
+    FileSort, InvertOptions, JsonFormat, MergeOptions, OutputFormat, OutputName, ReadOptions,
+    SimplifyAlgorithm, SimplifyOptions, WaypointNaming, WriteOptions, anonymize, center, decimate,
+    extract, from_geojson, gap_fill, get_merged_file_path, group_merge, head, info, invert,
+    invert_all, keep_longest, merge, merge_all, near_route, nearest, overlap, pipeline,
+    read_input_list, route_to_track, segments_to_tracks, simplify, stats, tail, to_csv, to_geojson,
+    to_kml, to_profile, to_waypoints, track_to_route, validate,
+};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use strum::VariantNames;
 
 #[derive(Parser)]
 #[command(author, version, about = "MMG - A tool to merge GPX files")]
@@ -9,32 +22,468 @@ struct Cli {
     #[arg(short, long, global = true)]
     verbose: bool,
 
+    /// Fail on the GPX files whose structure is broken, before processing them: the coordinates of all the points
+    /// must be valid (see the "validate" command), and there must be tracks, routes or waypoints, and a GPX version.
+    #[arg(long, global = true)]
+    strict_parse: bool,
+
+    #[command(flatten)]
+    write: WriteArgs,
+
+    #[command(flatten)]
+    json: JsonArgs,
+
     #[command(subcommand)]
     command: Command,
 }
 
 const HELP_FOR_FILES_ARG: &str = "A list of path to your GPX files (separated with spaces).";
 const HELP_FOR_DIRECTORY_ARG: &str = "The path of the directory where your GPX files are.";
+const HELP_FOR_SEGMENTS_ONLY_ARG: &str = "Only invert the order of the segments in each track, not the order of the points in each segment.";
+const HELP_FOR_SKIP_EMPTY_ARG: &str = "Do not create output files for the files without tracks (they would be identical to the input files).";
+const HELP_FOR_REPORT_ARG: &str = "Print the first and last points of each file before and after inverting, to check that the direction is flipped.";
+const HELP_FOR_COUNT_ARG: &str = "The number of points to print.";
+const HELP_FOR_TRACKPOINTS_ONLY_ARG: &str =
+    "Only export the points of the tracks, not the waypoints and the routes.";
+
+/// The help of the "--transforms" argument, listing the transformations of `TRANSFORM_NAMES`.
+fn get_transforms_help() -> String {
+    let (last, others) = TRANSFORM_NAMES
+        .split_last()
+        .expect("there are transformations");
+    let others = others
+        .iter()
+        .map(|name| format!("\"{name}\""))
+        .collect::<Vec<_>>();
+    format!(
+        "The transformations to apply, in order, separated by semicolons. They are: {} and \"{last}\". For instance: \"crop:bbox=45,5,46,6;decimate:10\"",
+        others.join(", ")
+    )
+}
+
+/// Parse a time in the RFC 3339 format, e.g. "2024-06-01T08:00:00Z".
+fn parse_time(value: &str) -> Result<time::OffsetDateTime, String> {
+    time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .map_err(|err| format!("expected a time like 2024-06-01T08:00:00Z ({err})"))
+}
+
+/// Create a parser for an enumeration of the library, accepting the names of its variants.
+fn enum_parser<E>() -> impl TypedValueParser<Value = E>
+where
+    E: VariantNames + FromStr + Clone + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(E::VARIANTS).map(|value| {
+        value
+            .parse::<E>()
+            .unwrap_or_else(|_| unreachable!("'{value}' is one of the possible values"))
+    })
+}
+
+/// Arguments for writing GPX files, accepted by all the commands.
+#[derive(Args)]
+struct WriteArgs {
+    /// Read each GPX file back after writing it, and check that it has the same tracks, segments and points.
+    #[arg(long, global = true)]
+    verify: bool,
+    /// Start each GPX file with a UTF-8 byte order mark (BOM), for the tools that require it.
+    #[arg(long, global = true)]
+    with_bom: bool,
+    /// Write GPX files without indentation, to make them smaller (for instance, to serve them on the web).
+    #[arg(long, global = true)]
+    compact: bool,
+    /// Remove the elevations of all the points of each GPX file written.
+    #[arg(long, global = true)]
+    strip_elevation: bool,
+    /// Round the elevations of all the points of each GPX file written to this number of decimals
+    /// (e.g. 1, since more is meaningless for most uses and bloats the files).
+    #[arg(long, global = true, value_name = "DIGITS", value_parser = clap::value_parser!(u8).range(0..=9))]
+    elevation_precision: Option<u8>,
+    /// Remove the times of all the points of each GPX file written.
+    #[arg(long, global = true)]
+    strip_time: bool,
+    /// Remove all the waypoints of each GPX file written (e.g. the ones generated by a device or a planner),
+    /// to get clean files with only tracks and routes.
+    #[arg(long, global = true)]
+    strip_waypoints: bool,
+    /// Remove the points at "Null Island" (0, 0) from each GPX file written, which broken recorders write
+    /// when they have no fix.
+    #[arg(long, global = true)]
+    drop_null_island: bool,
+    /// Do not write any file, only tell what would be written
+    /// (and, for the commands that transform files, how the points would change).
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Do not write any file, only tell the size (in bytes) that each GPX file would have. Implies --dry-run.
+    #[arg(long, global = true)]
+    estimate_size: bool,
+    /// Remove the segments with fewer than N points from each GPX file written,
+    /// such as the tiny leftovers of a decimation.
+    #[arg(long, global = true, value_name = "N")]
+    min_segment_points: Option<usize>,
+    /// Write the speed of each track point in each GPX file written, computed from the distance and the time
+    /// to the previous point, in a Garmin "TrackPointExtension" (the points without times get no speed).
+    #[arg(long, global = true)]
+    add_speed: bool,
+    /// Write each GPX file in pure ASCII, for legacy devices (e.g. old Garmin units): the non-ASCII characters
+    /// of the names, descriptions, comments, sources and links are written as XML references (e.g. "&#233;" for "é").
+    #[arg(long, global = true, conflicts_with = "with_bom")]
+    ascii: bool,
+    /// Give the root element of each GPX file written the location of the GPX schema ("xsi:schemaLocation"),
+    /// for the importers that validate the files against the XSD and reject the files without it.
+    #[arg(long, global = true)]
+    add_schema_location: bool,
+    /// Add this suffix to the names of the output files instead of the one of the command (e.g. "inverted").
+    /// "{action}" is replaced by the suffix of the command, e.g. "{action}-v2" gives "a-inverted-v2.gpx".
+    #[arg(long, global = true, value_name = "STRING", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    output_suffix: Option<String>,
+    /// Add the current time (in UTC) to the names of the output files, like "a-inverted-20240601-080000.gpx",
+    /// to keep the outputs of the previous runs instead of overwriting them.
+    #[arg(long, global = true)]
+    timestamped_output: bool,
+}
+
+impl WriteArgs {
+    fn options(&self) -> WriteOptions {
+        WriteOptions {
+            verify: self.verify,
+            with_bom: self.with_bom,
+            compact: self.compact,
+            strip_elevation: self.strip_elevation,
+            elevation_precision: self.elevation_precision,
+            strip_time: self.strip_time,
+            strip_waypoints: self.strip_waypoints,
+            drop_null_island: self.drop_null_island,
+            dry_run: self.dry_run || self.estimate_size,
+            estimate_size: self.estimate_size,
+            min_segment_points: self.min_segment_points,
+            add_speed: self.add_speed,
+            ascii: self.ascii,
+            add_schema_location: self.add_schema_location,
+            output_suffix: self.output_suffix.clone(),
+            output_timestamp: self.timestamped_output.then(time::OffsetDateTime::now_utc),
+        }
+    }
+}
+
+/// Arguments for printing JSON, accepted by all the commands.
+#[derive(Args)]
+struct JsonArgs {
+    /// Print the JSON documents indented, for humans.
+    #[arg(long, global = true, conflicts_with = "json_compact")]
+    json_pretty: bool,
+    /// Print the JSON documents on a single line, to pipe them to other tools (this is the default).
+    #[arg(long, global = true)]
+    json_compact: bool,
+}
+
+impl JsonArgs {
+    /// Get the format of the JSON documents, if `json` tells to print them.
+    fn format(&self, json: bool) -> Option<JsonFormat> {
+        match (json, self.json_pretty) {
+            (false, _) => None,
+            (true, true) => Some(JsonFormat::Pretty),
+            (true, false) => Some(JsonFormat::Compact),
+        }
+    }
+}
+
+/// Arguments of the commands that take a list of files, which can also be read from a file.
+#[derive(Args)]
+struct FilesArgs {
+    #[arg(required_unless_present = "input_list", num_args = 1.., help = HELP_FOR_FILES_ARG)]
+    files: Vec<PathBuf>,
+    /// Read the paths of the GPX files from this file, one per line (after the files given on the command-line, if any).
+    /// Blank lines and lines starting with '#' are ignored.
+    #[arg(long, value_name = "PATH")]
+    input_list: Option<PathBuf>,
+}
+
+impl FilesArgs {
+    fn files(&self) -> eyre::Result<Vec<PathBuf>> {
+        let mut files = self.files.clone();
+        if let Some(list) = &self.input_list {
+            files.extend(read_input_list(list)?);
+        }
+
+        if files.is_empty() {
+            return Err(eyre::eyre!("No files are given"));
+        }
+        Ok(files)
+    }
+}
+
+/// Arguments of the "*-all" commands, to select files in a directory.
+#[derive(Args)]
+struct DirectoryArgs {
+    #[arg(required = true, help = HELP_FOR_DIRECTORY_ARG)]
+    directory: PathBuf,
+    /// Only use the files whose names match this glob pattern (e.g. "ride-*.gpx").
+    /// Can be repeated: a file is used if it matches any of the patterns.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Do not use the files whose names match this glob pattern.
+    /// Can be repeated: a file is not used if it matches any of the patterns.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    #[command(flatten)]
+    sort: SortArgs,
+    /// Fail when no GPX files are found in the directory (instead of doing nothing), for instance in scripts.
+    #[arg(long)]
+    error_on_empty: bool,
+}
+
+impl DirectoryArgs {
+    fn filter(&self) -> FileFilter {
+        FileFilter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+}
+
+/// Arguments to choose the order in which files are processed.
+#[derive(Args)]
+struct SortArgs {
+    /// Order in which the files are processed (by default: by name for the "*-all" commands,
+    /// and in the order of the command line for the others).
+    /// "name" compares the bytes of the names (so "B.gpx" < "a.gpx" and "file10.gpx" < "file2.gpx"),
+    /// "natural" compares the numbers in the names as numbers (so "file2.gpx" < "file10.gpx"),
+    /// "mtime" uses the modification times of the files, and "time" the times of their first points
+    /// (then their names, for equal times).
+    #[arg(long, value_name = "ORDER", value_parser = enum_parser::<FileOrder>())]
+    sort_by: Option<FileOrder>,
+    /// Process the files in the reverse order.
+    #[arg(long)]
+    reverse: bool,
+}
+
+impl SortArgs {
+    fn sort(&self) -> FileSort {
+        FileSort {
+            by: self.sort_by,
+            reverse: self.reverse,
+        }
+    }
+}
+
+/// Arguments to name the data written in the output files.
+#[derive(Args)]
+struct NameArgs {
+    /// Name the output data (in its metadata) after the name of the output file.
+    #[arg(long, conflicts_with = "name")]
+    name_from_filename: bool,
+    /// Name the output data (in its metadata).
+    #[arg(long, value_name = "STRING")]
+    name: Option<String>,
+}
+
+impl NameArgs {
+    fn output_name(&self) -> Option<OutputName> {
+        match &self.name {
+            Some(name) => Some(OutputName::Custom(name.clone())),
+            None if self.name_from_filename => Some(OutputName::FromFilename),
+            None => None,
+        }
+    }
+}
+
+/// Arguments of the commands that remove points, and may leave tracks without points.
+#[derive(Args)]
+struct PruneArgs {
+    /// Remove the tracks left without points (e.g. by a crop).
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set)]
+    prune: bool,
+}
+
+/// Arguments of the "merge" and "merge-all" commands.
+#[derive(Args)]
+struct MergeArgs {
+    /// Also merge the routes of the files, as if they were tracks.
+    #[arg(long)]
+    routes_as_tracks: bool,
+    /// Also copy the routes of the files to the merged file, as routes (keeping their names),
+    /// e.g. to gather planned routes in a single file.
+    #[arg(long, conflicts_with = "routes_as_tracks")]
+    include_routes: bool,
+    /// Put the points of each file in a segment of their own,
+    /// instead of keeping the segments of the files.
+    #[arg(long)]
+    segment_per_file: bool,
+    /// Keep the tracks of the files as separate tracks (with their names, descriptions, links...),
+    /// instead of merging all their segments into a single track.
+    #[arg(long, conflicts_with = "segment_per_file")]
+    keep_tracks: bool,
+    /// Only merge one of the tracks that are (nearly) identical, for instance when a file was imported twice.
+    /// Tracks are considered identical if their points are within 10 meters of each other.
+    #[arg(long)]
+    dedup_tracks: bool,
+    /// Skip the files that cannot be read (e.g. invalid GPX files) instead of failing, and list them at the end.
+    #[arg(long)]
+    skip_invalid: bool,
+    /// Skip the files whose tracks are shorter than this (e.g. the accidental recordings of a few meters),
+    /// and list them.
+    #[arg(long, value_name = "METERS")]
+    min_length: Option<f64>,
+    /// Write a merged file per day (e.g. `merged-2024-06-01.gpx`), grouping the files by the date of their first point.
+    /// The files without times are merged in `merged-undated.gpx`.
+    #[arg(long)]
+    per_day: bool,
+    /// Write a merged file per chunk of N files (e.g. `merged-001.gpx`, `merged-002.gpx`...), in the order of the merge,
+    /// instead of a single enormous file. The last chunk may have fewer files.
+    #[arg(long, value_name = "N", conflicts_with = "per_day", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    chunk: Option<usize>,
+    /// Add a waypoint named like "Segment 2 start" at the start of each segment but the first,
+    /// to show where the recordings were interrupted.
+    #[arg(long)]
+    segment_break_markers: bool,
+    /// Add waypoints named "Start" and "Finish" at the first and last points of the merged track, e.g. for route sheets.
+    #[arg(long)]
+    start_finish_markers: bool,
+    /// Add a waypoint named "Summary" at the start of the merged track, whose description tells its distance,
+    /// elevation gain and duration, for the map apps that show the descriptions of waypoints.
+    #[arg(long)]
+    summary_waypoint: bool,
+    /// Add a waypoint named like "point #100" at every N-th track point, to find a region of the merged track by index.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    checkpoint_every: Option<usize>,
+    /// Concatenate the excess segments of the merged track when it would have more than N segments,
+    /// since some tools choke on tracks with hundreds of segments.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_segments: Option<usize>,
+    /// Fail instead of concatenating segments, when the merged track would have more than "--max-segments" segments.
+    #[arg(long, requires = "max_segments")]
+    strict: bool,
+    /// Decimate the merged tracks so that they have about N points in total; each track is decimated
+    /// to a share of N proportional to its number of points (e.g. with "--keep-tracks", so that none dominates).
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_points: Option<usize>,
+    /// Remove the merged points closer than this distance to a point kept before them, in any file,
+    /// to thin the clusters of near-identical points where the recordings overlap.
+    #[arg(long, value_name = "METERS")]
+    dedup_points: Option<f64>,
+    /// Print the distance between the end of each file and the start of the next one,
+    /// with a warning when it is more than "--gap-warning" (e.g. when the files are in the wrong order).
+    #[arg(long)]
+    check_continuity: bool,
+    /// With "--check-continuity", warn about the distances between files that are more than this.
+    #[arg(
+        long,
+        value_name = "METERS",
+        default_value_t = 100.0,
+        requires = "check_continuity"
+    )]
+    gap_warning: f64,
+    /// Copy the metadata of this GPX file (author, copyright, keywords...) to the merged file, except its time and bounds.
+    #[arg(long, value_name = "PATH")]
+    metadata_from: Option<PathBuf>,
+    /// Shift the times of the merged points so that the earliest one is this time (e.g. "2000-01-01T00:00:00Z"),
+    /// keeping their spacing: to hide the dates, or to compare activities on a common timeline.
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    rebase_time: Option<time::OffsetDateTime>,
+    #[command(flatten)]
+    name: NameArgs,
+    /// Comment of the merged track (of every track, with "--keep-tracks"), shown by many viewers.
+    #[arg(long, value_name = "STRING")]
+    comment: Option<String>,
+    /// Description of the merged track (of every track, with "--keep-tracks"), shown by many viewers.
+    #[arg(long, value_name = "STRING")]
+    description: Option<String>,
+    /// Format of the output file.
+    #[arg(long, value_name = "FORMAT", value_parser = enum_parser::<OutputFormat>())]
+    output_format: Option<OutputFormat>,
+    /// Write a JSON file listing the merged files (with their numbers of points) and the date of the merge.
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+}
+
+impl MergeArgs {
+    fn options(&self) -> MergeOptions {
+        MergeOptions {
+            routes_as_tracks: self.routes_as_tracks,
+            include_routes: self.include_routes,
+            segment_per_file: self.segment_per_file,
+            keep_tracks: self.keep_tracks,
+            dedup_tracks: self.dedup_tracks,
+            skip_invalid: self.skip_invalid,
+            min_length_m: self.min_length,
+            per_day: self.per_day,
+            chunk_size: self.chunk,
+            segment_break_markers: self.segment_break_markers,
+            start_finish_markers: self.start_finish_markers,
+            summary_waypoint: self.summary_waypoint,
+            checkpoint_every: self.checkpoint_every,
+            max_segments: self.max_segments,
+            strict_max_segments: self.strict,
+            max_points: self.max_points,
+            dedup_points_m: self.dedup_points,
+            check_continuity_m: self.check_continuity.then_some(self.gap_warning),
+            metadata_from: self.metadata_from.clone(),
+            rebase_time: self.rebase_time,
+            name: self.name.output_name(),
+            comment: self.comment.clone(),
+            description: self.description.clone(),
+            output_format: self.output_format,
+            manifest: self.manifest.clone(),
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Subcommand)]
 enum Command {
     /// Merge all tracks from all given files into a file with a single track.
     ///
-    /// File are merged by order of appearance on the command-line.
-    /// The output file `merged.gpx` is created in the current directory.
+    /// Files are merged by order of appearance on the command-line (unless "--sort-by" is given),
+    /// and the tracks, segments and points of each file stay in their order.
+    /// The output file `merged.gpx` (or with another extension, depending on `--output-format`) is created in the current directory
+    /// ("--output-suffix" and "--timestamped-output" change its name as for the other commands).
     Merge {
-        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
-        files: Vec<PathBuf>,
+        #[command(flatten)]
+        files: FilesArgs,
+        #[command(flatten)]
+        sort: SortArgs,
+        #[command(flatten)]
+        merge: MergeArgs,
     },
 
     /// Same as the "merge" command with all the files in the given directory.
     ///
-    /// Files are merged by alphabetical order of their names.
-    /// The output file `merged.gpx` is created in `directory`.
+    /// Files are merged by order of their names, comparing their bytes (unless "--sort-by" is given).
+    /// The output file `merged.gpx` (or with another extension, depending on `--output-format`) is created in `directory`
+    /// ("--output-suffix" and "--timestamped-output" change its name as for the other commands).
     #[command(name = "merge-all")]
     MergeAll {
-        #[arg(required = true, help = HELP_FOR_DIRECTORY_ARG)]
-        directory: PathBuf,
+        #[command(flatten)]
+        directory: DirectoryArgs,
+        #[command(flatten)]
+        merge: MergeArgs,
+        /// Start a new segment only when the time between the end of a file
+        /// and the start of the next one exceeds this number of seconds (or when the next one starts before the end).
+        /// Otherwise, the last segment of a file and the first segment of the next one are joined
+        /// (the other segments of the files, e.g. at the pauses, are kept).
+        /// Unless "--sort-by" is given, the files are ordered by time.
+        #[arg(long, value_name = "SECONDS", conflicts_with = "keep_tracks")]
+        gap: Option<u64>,
+    },
+
+    /// Same as the "merge-all" command, merging the files of the directory by groups.
+    ///
+    /// The files whose names give the same key with "--group-regex" are merged into `<key>.gpx`
+    /// (or with another extension, depending on `--output-format`) in `directory`.
+    /// With "--output-suffix" or "--timestamped-output", the suffix is added to the key, e.g. `<key>-merged-v2.gpx`.
+    /// The files whose names do not match are skipped.
+    #[command(name = "group-merge")]
+    GroupMerge {
+        #[command(flatten)]
+        directory: DirectoryArgs,
+        /// Regular expression giving the key of the group of each file from its name: the part matched by the first
+        /// capture group, or by the whole expression if it has no groups.
+        /// For instance, "^(\d{4}-\d{2}-\d{2})-" merges "2024-06-01-morning.gpx" and "2024-06-01-evening.gpx"
+        /// into "2024-06-01.gpx".
+        #[arg(long, value_name = "PATTERN")]
+        group_regex: String,
+        #[command(flatten)]
+        merge: MergeArgs,
     },
 
     /// Invert each track of each given file.
@@ -44,34 +493,437 @@ enum Command {
     Invert {
         #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
         files: Vec<PathBuf>,
+        #[arg(long, help = HELP_FOR_SEGMENTS_ONLY_ARG)]
+        segments_only: bool,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+        #[arg(long, help = HELP_FOR_REPORT_ARG)]
+        report: bool,
     },
 
     /// Same as the "invert" command with all the files in the given directory.
     #[command(name = "invert-all")]
     InvertAll {
-        #[arg(required = true, help = HELP_FOR_DIRECTORY_ARG)]
-        directory: PathBuf,
+        #[command(flatten)]
+        directory: DirectoryArgs,
+        #[arg(long, help = HELP_FOR_SEGMENTS_ONLY_ARG)]
+        segments_only: bool,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+        #[arg(long, help = HELP_FOR_REPORT_ARG)]
+        report: bool,
     },
 
     /// Decimate the points of each (segment of each) track of each given file, to reduce their size.
-    /// 
+    ///
     /// For instance, Komoot cannot import a GPX file with too many points, and shows an error message like:
-    /// 
+    ///
     /// "There’s an issue with your file. It’s either too large or contains too many waypoints.
     /// Try importing multiple smaller files instead."
-    /// 
+    ///
     /// You can use this command to reduce the number of points until Komoot is happy.
     Decimate {
+        #[command(flatten)]
+        files: FilesArgs,
+        /// Decimate by a factor M; that is, keep only every M-th point.
+        #[arg(long, value_name = "M")]
+        factor: u16,
+        /// Keep the points at the indices K, K + M, K + 2 M... of each segment, instead of starting at the first point
+        /// (the last point is still kept), e.g. to compare sampling strategies or align the outputs of related files.
+        #[arg(long, value_name = "K", default_value_t = 0)]
+        offset: usize,
+        /// Decimate the segments that would still have more than N points by a larger factor,
+        /// computed for each segment (printed with "--verbose"), for the tools that limit the points per segment.
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        max_points_per_segment: Option<usize>,
+        /// Never keep less than N points in a file: the factor is reduced for the files where it would happen.
+        #[arg(long, value_name = "N")]
+        min_points: Option<usize>,
+        /// After decimating, keep each point with this probability (between 0 and 1), instead of regularly;
+        /// the first and last points of the segments are always kept. Use a factor of 1 to only sample randomly.
+        #[arg(long, value_name = "FRACTION")]
+        random: Option<f64>,
+        /// The seed of the random sampling: the same seed always keeps the same points.
+        #[arg(long, default_value_t = 0, requires = "random")]
+        seed: u64,
+        /// After decimating, remove the spikes: the points that are further than this distance from both their neighbors.
+        #[arg(long, value_name = "METERS")]
+        despike: Option<f64>,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+    },
+
+    /// Fill the gaps of each given file, where the recording was interrupted, with interpolated points.
+    ///
+    /// An output file is created per input file.
+    /// The positions, elevations and times of the new points are interpolated linearly.
+    GapFill {
         #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
         files: Vec<PathBuf>,
-        /// Decimate by a factor M; that is, keep only every M-th point.
-        factor_m: u16,
+        /// Fill the gaps longer than this distance between consecutive points.
+        #[arg(long, value_name = "METERS")]
+        max_gap: f64,
+        /// The distance between the interpolated points.
+        #[arg(long, value_name = "METERS")]
+        step: f64,
+        /// Leave the gaps longer than this distance alone, since they are likely genuine breaks.
+        #[arg(long, value_name = "METERS")]
+        limit: Option<f64>,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
     },
 
-    /// Print information about one or more GPX files.
-    Info {
+    /// Simplify the tracks of each given file, removing the points that barely change their shape.
+    ///
+    /// An output file is created per input file.
+    /// The first and last points of the segments are always kept.
+    Simplify {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// "rdp" (Ramer-Douglas-Peucker) removes the points closer than "--tolerance" to the simplified line.
+        /// "visvalingam" (Visvalingam-Whyatt) removes the point forming the smallest triangle with its neighbors,
+        /// again and again, until "--min-area" or "--max-points" is reached; it often preserves the overall shape better.
+        #[arg(long, value_name = "ALGORITHM", default_value_t = SimplifyAlgorithm::Rdp, value_parser = enum_parser::<SimplifyAlgorithm>())]
+        algorithm: SimplifyAlgorithm,
+        /// With "rdp", the maximum distance between the removed points and the simplified line.
+        #[arg(long, value_name = "METERS")]
+        tolerance: Option<f64>,
+        /// With "visvalingam", stop when the smallest triangle has at least this area.
+        #[arg(long, value_name = "SQUARE_METERS")]
+        min_area: Option<f64>,
+        /// With "visvalingam", stop when each segment has at most this number of points.
+        #[arg(long, value_name = "N")]
+        max_points: Option<usize>,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+    },
+
+    /// Apply several transformations to each given file, in a single pass.
+    ///
+    /// An output file is created per input file.
+    /// The extensions of the tracks are not kept.
+    Pipeline {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        #[arg(long, required = true, value_name = "PIPELINE", help = get_transforms_help())]
+        transforms: String,
+        #[command(flatten)]
+        prune: PruneArgs,
+    },
+
+    /// Remove personal data from each given file, before sharing it publicly.
+    ///
+    /// An output file is created per input file.
+    /// The metadata (author, links, time...), the times of the points, their elevations
+    /// and the sources (recording devices) are removed.
+    Anonymize {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Keep the elevations of the points.
+        #[arg(long)]
+        keep_elevation: bool,
+    },
+
+    /// Extract the track points of each given file in a range of times or of indices, into a new file.
+    ///
+    /// An output file is created per input file, the input files are not modified.
+    /// The routes and the waypoints are kept.
+    Extract {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Only keep the points from this time (included), e.g. "2024-06-01T08:00:00Z".
+        #[arg(long, value_name = "TIME", value_parser = parse_time, conflicts_with_all = ["start_index", "end_index"])]
+        after: Option<time::OffsetDateTime>,
+        /// Only keep the points until this time (included), e.g. "2024-06-01T09:30:00+02:00".
+        #[arg(long, value_name = "TIME", value_parser = parse_time, conflicts_with_all = ["start_index", "end_index"])]
+        before: Option<time::OffsetDateTime>,
+        /// Only keep the points from this index (included), counting the points of all the tracks from 0
+        /// (see the "head" command).
+        #[arg(long, value_name = "INDEX")]
+        start_index: Option<usize>,
+        /// Only keep the points until this index (included).
+        #[arg(long, value_name = "INDEX")]
+        end_index: Option<usize>,
+        #[command(flatten)]
+        prune: PruneArgs,
+    },
+
+    /// Only keep the points of each given file that are near the tracks and routes of a reference file,
+    /// e.g. to clip a long recording down to the section that follows a known trail.
+    ///
+    /// An output file is created per input file.
+    /// The segments are split where points are removed, so that no straight lines are drawn over them.
+    #[command(name = "near-route")]
+    NearRoute {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// The GPX file with the reference tracks or routes.
+        #[arg(long, value_name = "PATH")]
+        reference: PathBuf,
+        /// The maximum distance (in meters) of the kept points from the lines of the reference.
+        #[arg(long, value_name = "METERS", default_value_t = 50.0)]
+        tolerance: f64,
+        #[command(flatten)]
+        prune: PruneArgs,
+    },
+
+    /// Only keep the longest track (or segment) of each given file, to clean up the stray fragments
+    /// that some devices create.
+    ///
+    /// An output file is created per input file.
+    /// The routes and the waypoints are kept.
+    #[command(name = "keep-longest")]
+    KeepLongest {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Only keep the longest segment of all the tracks (in its track), instead of the longest track.
+        #[arg(long)]
+        by_segment: bool,
+        /// Compare the distances of the tracks (or segments), instead of their numbers of points.
+        #[arg(long)]
+        by_distance: bool,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+    },
+
+    /// Make a track of each segment of each track of each given file, for the tools that ignore segments
+    /// and show each track apart (e.g. with its own color).
+    ///
+    /// An output file is created per input file.
+    /// The new tracks keep the name of their track, followed by the number of the segment, like "Ride - segment 2".
+    #[command(name = "segments-to-tracks")]
+    SegmentsToTracks {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = HELP_FOR_SKIP_EMPTY_ARG)]
+        skip_empty: bool,
+    },
+
+    /// Convert the routes of each given file into tracks.
+    ///
+    /// An output file is created per input file.
+    /// Each route becomes a track with a single segment; existing tracks are kept.
+    #[command(name = "route-to-track")]
+    RouteToTrack {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Convert the tracks of each given file into routes, for turn-by-turn planning tools.
+    ///
+    /// An output file is created per input file.
+    /// Each track becomes a route with the points of all its segments; existing routes are kept.
+    #[command(name = "track-to-route")]
+    TrackToRoute {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Decimate the tracks by a factor M before converting them, so that the routes stay manageable.
+        #[arg(long, value_name = "M", value_parser = clap::value_parser!(u16).range(1..))]
+        decimate: Option<u16>,
+    },
+
+    /// Convert the points of the tracks of each given file into waypoints.
+    ///
+    /// An output file with only waypoints is created per input file,
+    /// for planning tools that do not accept tracks.
+    #[command(name = "to-waypoints")]
+    ToWaypoints {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Only keep every N-th point of each segment (and its last point).
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        every: u16,
+        /// How to name the waypoints.
+        #[arg(long, value_name = "NAMING", default_value = "index", value_parser = enum_parser::<WaypointNaming>())]
+        name_by: WaypointNaming,
+    },
+
+    /// Convert GeoJSON files to GPX files.
+    ///
+    /// Each file must contain a FeatureCollection.
+    /// LineString and MultiLineString features become tracks, Point features become waypoints.
+    /// An output file is created next to each input file.
+    #[command(name = "from-geojson")]
+    FromGeojson {
+        /// A list of path to your GeoJSON files (separated with spaces).
+        #[arg(required = true, num_args = 1..)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        name: NameArgs,
+    },
+
+    /// Print the center of the tracks and routes of each given file.
+    ///
+    /// The center is the average of the points, computed on the sphere
+    /// (so that it makes sense even for tracks crossing the antimeridian).
+    Center {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Weight the points by the length of the track around them,
+        /// so that densely recorded parts do not attract the center.
+        #[arg(long)]
+        weighted: bool,
+        /// Print the result as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Convert each given file to GeoJSON.
+    ///
+    /// A GeoJSON file with the same name (or with the suffix of "--output-suffix" or "--timestamped-output")
+    /// is created next to each input file.
+    /// Tracks become MultiLineString features, routes become LineString features, and waypoints become Point features.
+    #[command(name = "to-geojson")]
+    ToGeojson {
         #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
         files: Vec<PathBuf>,
+        #[arg(long, help = HELP_FOR_TRACKPOINTS_ONLY_ARG)]
+        trackpoints_only: bool,
+        /// Order of the coordinates of the positions.
+        /// The default is the order required by the GeoJSON specification; use "latlon" only for tools that expect it.
+        #[arg(long, value_name = "ORDER", default_value = "lonlat", value_parser = enum_parser::<AxisOrder>())]
+        lat_lon_order: AxisOrder,
+        #[command(flatten)]
+        name: NameArgs,
+    },
+
+    /// Convert each given file to KML, for Google Earth.
+    ///
+    /// A KML file with the same name (or with the suffix of "--output-suffix" or "--timestamped-output")
+    /// is created next to each input file.
+    #[command(name = "to-kml")]
+    ToKml {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Color the lines of the tracks by speed or elevation, from green (lowest) to red (highest).
+        /// Each track is split into a placemark per band.
+        #[arg(long, value_name = "METRIC", value_parser = enum_parser::<ColorBy>())]
+        color_by: Option<ColorBy>,
+        /// Number of bands of equal width between the minimum and the maximum of the metric.
+        #[arg(long, value_name = "N", default_value_t = 5, requires = "color_by", value_parser = clap::value_parser!(u8).range(1..))]
+        bands: u8,
+        #[command(flatten)]
+        name: NameArgs,
+    },
+
+    /// Convert each given file to CSV, with a row per point.
+    ///
+    /// A CSV file with the same name (or with the suffix of "--output-suffix" or "--timestamped-output")
+    /// is created next to each input file.
+    #[command(name = "to-csv")]
+    ToCsv {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = HELP_FOR_TRACKPOINTS_ONLY_ARG)]
+        trackpoints_only: bool,
+        /// Order of the latitude and longitude columns.
+        #[arg(long, value_name = "ORDER", default_value = "latlon", value_parser = enum_parser::<AxisOrder>())]
+        lat_lon_order: AxisOrder,
+    },
+
+    /// Export the elevation profile of each given file as plain text, for plotting tools like gnuplot.
+    ///
+    /// A file named like `ride-profile.txt` is created next to each input file, with a line per track point:
+    /// the cumulative distance and the elevation, separated by a space.
+    /// The points without elevation are skipped.
+    #[command(name = "to-profile")]
+    ToProfile {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Unit of the distances.
+        #[arg(long, value_name = "UNIT", default_value = "km", value_parser = enum_parser::<DistanceUnit>())]
+        units: DistanceUnit,
+        /// Unit of the elevations.
+        #[arg(long, value_name = "UNIT", default_value = "m", value_parser = enum_parser::<ElevationUnit>())]
+        elevation_units: ElevationUnit,
+    },
+
+    /// Print statistics about the tracks of each given file: distance, duration...
+    Stats {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Also print the time of each kilometer (or mile) of the tracks, like a sports watch.
+        #[arg(long, value_name = "UNIT", value_parser = enum_parser::<DistanceUnit>())]
+        splits: Option<DistanceUnit>,
+        /// Minimum speed to count the time as moving time; the rest of the duration is the stopped time.
+        #[arg(long, value_name = "METERS_PER_SECOND", default_value_t = 0.5)]
+        moving_threshold: f64,
+        /// Also print the start, from the time of the metadata of the file when the points have no times
+        /// (e.g. for old files).
+        #[arg(long)]
+        prefer_file_time: bool,
+        /// Also print an estimate of the duration at this speed, computed from the distance (e.g. to plan a ride).
+        #[arg(long, value_name = "KMH")]
+        assume_speed: Option<f64>,
+    },
+
+    /// Tell how much the tracks of two files overlap, e.g. to decide whether to merge them or to dedup them.
+    ///
+    /// The ratio of the track points of each file that are close to the tracks of the other file is printed,
+    /// both ways.
+    Overlap {
+        /// The path of the first GPX file.
+        first_file: PathBuf,
+        /// The path of the second GPX file.
+        second_file: PathBuf,
+        /// Maximum distance from a point to the tracks of the other file to count it as overlapping.
+        #[arg(long, value_name = "METERS", default_value_t = 10.0)]
+        tolerance: f64,
+    },
+
+    /// Print the points of the tracks of each given file that are the closest to a location (e.g. a summit).
+    Nearest {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Latitude of the location, in degrees.
+        #[arg(long, allow_negative_numbers = true)]
+        lat: f64,
+        /// Longitude of the location, in degrees.
+        #[arg(long, allow_negative_numbers = true)]
+        lon: f64,
+        /// The number of points to print, from the closest.
+        #[arg(short = 'n', long, value_name = "K", default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Print the first points of the tracks of each given file.
+    Head {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 10, help = HELP_FOR_COUNT_ARG)]
+        count: usize,
+    },
+
+    /// Print the last points of the tracks of each given file.
+    Tail {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 10, help = HELP_FOR_COUNT_ARG)]
+        count: usize,
+    },
+
+    /// Check the coordinates of the points of each given file.
+    ///
+    /// The points whose coordinates are both zero (as written by broken recorders without a fix),
+    /// not numbers or out of range are listed.
+    Validate {
+        #[arg(required = true, num_args = 1.., help = HELP_FOR_FILES_ARG)]
+        files: Vec<PathBuf>,
+        /// Fail if any file has points with invalid coordinates.
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Print information about one or more GPX files.
+    Info {
+        #[command(flatten)]
+        files: FilesArgs,
+        /// Print a single JSON array describing all the files, instead of the human-readable output.
+        #[arg(long, alias = "output-stdout-json")]
+        json: bool,
+        /// Detail at most N waypoints, routes, and segments per track (the totals still count all of them).
+        #[arg(long, value_name = "N", conflicts_with = "json")]
+        limit: Option<usize>,
     },
 }
 
@@ -80,18 +932,322 @@ fn main() -> eyre::Result<()> {
 
     let cli = Cli::parse();
 
+    let read_options = ReadOptions {
+        strict: cli.strict_parse,
+    };
+    let write_options = cli.write.options();
+
     let execution_result = match &cli.command {
-        Command::Invert { files } => invert(files),
-        Command::InvertAll { directory } => invert_all(directory),
-        Command::Merge { files } => merge(files, &std::env::current_dir()?.join("merged.gpx")),
-        Command::MergeAll { directory } => merge_all(directory),
-        Command::Info { files } => info(files),
-        Command::Decimate { files, factor_m} => decimate(files, *factor_m),
+        Command::Invert {
+            files,
+            segments_only,
+            skip_empty,
+            report,
+        } => invert(
+            files,
+            &InvertOptions {
+                segments_only: *segments_only,
+                skip_empty: *skip_empty,
+                report: *report,
+            },
+            &read_options,
+            &write_options,
+        ),
+        Command::InvertAll {
+            directory,
+            segments_only,
+            skip_empty,
+            report,
+        } => invert_all(
+            &directory.directory,
+            &directory.filter(),
+            directory.sort.sort(),
+            directory.error_on_empty,
+            &InvertOptions {
+                segments_only: *segments_only,
+                skip_empty: *skip_empty,
+                report: *report,
+            },
+            &read_options,
+            &write_options,
+        ),
+        Command::Merge {
+            files,
+            sort,
+            merge: args,
+        } => files.files().and_then(|files| {
+            merge(
+                &files,
+                &get_merged_file_path(&std::env::current_dir()?, &write_options),
+                &MergeOptions {
+                    sort: sort.sort(),
+                    ..args.options()
+                },
+                &read_options,
+                &write_options,
+            )
+        }),
+        Command::GroupMerge {
+            directory,
+            group_regex,
+            merge: args,
+        } => group_merge(
+            &directory.directory,
+            &directory.filter(),
+            directory.error_on_empty,
+            group_regex,
+            &MergeOptions {
+                sort: directory.sort.sort(),
+                ..args.options()
+            },
+            &read_options,
+            &write_options,
+        ),
+        Command::MergeAll {
+            directory,
+            merge: args,
+            gap,
+        } => merge_all(
+            &directory.directory,
+            &directory.filter(),
+            directory.error_on_empty,
+            &MergeOptions {
+                sort: directory.sort.sort(),
+                max_gap: gap.map(Duration::from_secs),
+                ..args.options()
+            },
+            &read_options,
+            &write_options,
+        ),
+        Command::Info { files, json, limit } => files
+            .files()
+            .and_then(|files| info(&files, cli.json.format(*json), *limit, &read_options)),
+        Command::Validate { files, strict } => validate(files, *strict),
+        Command::Stats {
+            files,
+            splits,
+            moving_threshold,
+            prefer_file_time,
+            assume_speed,
+        } => stats(
+            files,
+            *splits,
+            *moving_threshold,
+            *prefer_file_time,
+            *assume_speed,
+            &read_options,
+        ),
+        Command::Overlap {
+            first_file,
+            second_file,
+            tolerance,
+        } => overlap(first_file, second_file, *tolerance, &read_options),
+        Command::Nearest {
+            files,
+            lat,
+            lon,
+            count,
+        } => nearest(files, *lat, *lon, *count, &read_options),
+        Command::Head { files, count } => head(files, *count, &read_options),
+        Command::Tail { files, count } => tail(files, *count, &read_options),
+        Command::ToGeojson {
+            files,
+            trackpoints_only,
+            lat_lon_order,
+            name,
+        } => to_geojson(
+            files,
+            *trackpoints_only,
+            *lat_lon_order,
+            name.output_name().as_ref(),
+            &read_options,
+            &write_options,
+        ),
+        Command::ToKml {
+            files,
+            color_by,
+            bands,
+            name,
+        } => to_kml(
+            files,
+            *color_by,
+            usize::from(*bands),
+            name.output_name().as_ref(),
+            &read_options,
+            &write_options,
+        ),
+        Command::ToCsv {
+            files,
+            trackpoints_only,
+            lat_lon_order,
+        } => to_csv(
+            files,
+            *trackpoints_only,
+            *lat_lon_order,
+            &read_options,
+            &write_options,
+        ),
+        Command::ToProfile {
+            files,
+            units,
+            elevation_units,
+        } => to_profile(
+            files,
+            *units,
+            *elevation_units,
+            &read_options,
+            &write_options,
+        ),
+        Command::Center {
+            files,
+            weighted,
+            json,
+        } => center(files, *weighted, cli.json.format(*json), &read_options),
+        Command::Decimate {
+            files,
+            factor,
+            offset,
+            max_points_per_segment,
+            min_points,
+            random,
+            seed,
+            despike,
+            skip_empty,
+        } => files.files().and_then(|files| {
+            decimate(
+                &files,
+                *factor,
+                &DecimateOptions {
+                    offset: *offset,
+                    min_points: *min_points,
+                    max_points_per_segment: *max_points_per_segment,
+                    random_fraction: *random,
+                    seed: *seed,
+                    despike_m: *despike,
+                    skip_empty: *skip_empty,
+                },
+                cli.verbose,
+                &read_options,
+                &write_options,
+            )
+        }),
+        Command::GapFill {
+            files,
+            max_gap,
+            step,
+            limit,
+            skip_empty,
+        } => gap_fill(
+            files,
+            *max_gap,
+            *step,
+            *limit,
+            *skip_empty,
+            &read_options,
+            &write_options,
+        ),
+        Command::Simplify {
+            files,
+            algorithm,
+            tolerance,
+            min_area,
+            max_points,
+            skip_empty,
+        } => simplify(
+            files,
+            *algorithm,
+            &SimplifyOptions {
+                tolerance_m: *tolerance,
+                min_area_m2: *min_area,
+                max_points: *max_points,
+                skip_empty: *skip_empty,
+            },
+            &read_options,
+            &write_options,
+        ),
+        Command::Anonymize {
+            files,
+            keep_elevation,
+        } => anonymize(files, *keep_elevation, &read_options, &write_options),
+        Command::Extract {
+            files,
+            after,
+            before,
+            start_index,
+            end_index,
+            prune,
+        } => {
+            let range = if after.is_some() || before.is_some() {
+                PointRange::Times {
+                    after: *after,
+                    before: *before,
+                }
+            } else {
+                PointRange::Indices {
+                    start: *start_index,
+                    end: *end_index,
+                }
+            };
+            extract(files, range, prune.prune, &read_options, &write_options)
+        }
+        Command::NearRoute {
+            files,
+            reference,
+            tolerance,
+            prune,
+        } => near_route(
+            files,
+            reference,
+            *tolerance,
+            prune.prune,
+            &read_options,
+            &write_options,
+        ),
+        Command::KeepLongest {
+            files,
+            by_segment,
+            by_distance,
+            skip_empty,
+        } => keep_longest(
+            files,
+            *by_segment,
+            *by_distance,
+            *skip_empty,
+            &read_options,
+            &write_options,
+        ),
+        Command::Pipeline {
+            files,
+            transforms,
+            prune,
+        } => pipeline(
+            files,
+            transforms,
+            prune.prune,
+            &read_options,
+            &write_options,
+        ),
+        Command::SegmentsToTracks { files, skip_empty } => {
+            segments_to_tracks(files, *skip_empty, &read_options, &write_options)
+        }
+        Command::RouteToTrack { files } => route_to_track(files, &read_options, &write_options),
+        Command::TrackToRoute { files, decimate } => {
+            track_to_route(files, *decimate, &read_options, &write_options)
+        }
+        Command::ToWaypoints {
+            files,
+            every,
+            name_by,
+        } => to_waypoints(files, *every, *name_by, &read_options, &write_options),
+        Command::FromGeojson { files, name } => {
+            from_geojson(files, name.output_name().as_ref(), &write_options)
+        }
     };
 
     match execution_result {
         Ok(_) => {
-            println!("*** OK ***");
+            // Not on stdout, where some commands print JSON.
+            eprintln!("*** OK ***");
         }
         Err(err) => {
             if cli.verbose {
@@ -99,6 +1255,9 @@ fn main() -> eyre::Result<()> {
             } else {
                 eprintln!("*** Error: {} ***", err);
             }
+
+            // So that scripts can tell that the command failed.
+            std::process::exit(1);
         }
     }
 
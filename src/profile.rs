@@ -0,0 +1,74 @@
+//! Export of the elevation profile of GPX data as plain text.
+
+use crate::{DistanceUnit, ElevationUnit, distance};
+use std::io::Write;
+
+/// Write the elevation profile of the tracks, with a line per point: the cumulative distance and the elevation,
+/// separated by a space, for plotting tools like gnuplot.
+///
+/// The distance is in `distance_unit`, and the elevation in `elevation_unit`.
+/// The distance is cumulated along the segments of the tracks (not across the gaps between them).
+/// The points without elevation are skipped, but the distance to them is still counted.
+/// The first line is a comment (starting with `#`) naming the columns.
+pub(crate) fn write_profile(
+    gpx: &gpx::Gpx,
+    writer: &mut impl Write,
+    distance_unit: DistanceUnit,
+    elevation_unit: ElevationUnit,
+) -> eyre::Result<()> {
+    writeln!(
+        writer,
+        "# distance ({distance_unit}) elevation ({elevation_unit})"
+    )?;
+
+    let mut distance_m = 0.0;
+
+    for segment in gpx.tracks.iter().flat_map(|track| &track.segments) {
+        let mut previous: Option<&gpx::Waypoint> = None;
+
+        for point in &segment.points {
+            if let Some(previous) = previous {
+                distance_m += distance(previous, point);
+            }
+            previous = Some(point);
+
+            if let Some(elevation_m) = point.elevation {
+                writeln!(
+                    writer,
+                    "{:.3} {:.1}",
+                    distance_unit.from_meters(distance_m),
+                    elevation_unit.from_meters(elevation_m)
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{make_gpx, make_track};
+
+    #[test]
+    fn profiles_are_written_in_the_units() {
+        let mut gpx = make_gpx(vec![make_track("Climb", &[&[(45.0, 5.0), (45.01, 5.0)]])]);
+        for (point, elevation) in gpx.tracks[0].segments[0]
+            .points
+            .iter_mut()
+            .zip([100.0, 400.0])
+        {
+            point.elevation = Some(elevation);
+        }
+
+        let mut buffer = Vec::new();
+        write_profile(&gpx, &mut buffer, DistanceUnit::Mi, ElevationUnit::Ft).unwrap();
+
+        // 0.01 degree of latitude is about 1.112 km, or 0.691 mile.
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "# distance (mi) elevation (ft)\n0.000 328.1\n0.691 1312.3\n"
+        );
+    }
+}
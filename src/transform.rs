@@ -0,0 +1,1490 @@
+//! Transformations of GPX data, which can be chained to process a file in a single pass.
+//!
+//! The commands that modify files are built on them, and library users can compose them:
+//!
+//! ```no_run
+//! use merge_my_gpx::BoundingBox;
+//! use merge_my_gpx::transform::{Crop, Decimate, Transform};
+//!
+//! let transforms: Vec<Box<dyn Transform>> = vec![
+//!     Box::new(Crop {
+//!         bbox: BoundingBox { min_lat: 45.0, min_lon: 5.0, max_lat: 46.0, max_lon: 6.0 },
+//!     }),
+//!     Box::new(Decimate { factor_m: 10, offset: 0, max_points_per_segment: None }),
+//! ];
+//!
+//! let mut gpx = merge_my_gpx::read_gpx_file(&"ride.gpx", &Default::default())?;
+//! transforms.apply(&mut gpx)?;
+//! merge_my_gpx::save_gpx(&gpx, &"ride-cleaned.gpx", &Default::default())?;
+//! # Ok::<(), eyre::Report>(())
+//! ```
+
+use crate::{
+    Action, BoundingBox, LineIndex, count_segment_points_after_decimation, decimate_segment,
+    distance, distance_to_line, for_each_point_mut, get_creator, get_grid_cell_of,
+    get_grid_cells_near, get_grid_step, interpolate_point, is_at_null_island, route_into_track,
+    track_into_route, triangle_area,
+};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use time::OffsetDateTime;
+
+/// A transformation of GPX data.
+pub trait Transform {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()>;
+}
+
+/// Apply transformations in sequence.
+impl Transform for Vec<Box<dyn Transform>> {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for transform in self {
+            transform.apply(gpx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Invert the order of the tracks, of their segments and of the points of the segments.
+/// The names of the tracks get a suffix telling they were inverted.
+pub struct Invert {
+    /// Only invert the order of the segments in each track, not the order of the tracks and of the points.
+    pub segments_only: bool,
+}
+
+impl Transform for Invert {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let action = if self.segments_only {
+            Action::InvertSegments
+        } else {
+            Action::Invert
+        };
+
+        if !self.segments_only {
+            gpx.tracks.reverse();
+        }
+
+        for track in &mut gpx.tracks {
+            track.name = track.name.clone().map(|name| format!("{name} ({action})"));
+            track.segments.reverse();
+
+            if !self.segments_only {
+                for segment in &mut track.segments {
+                    segment.points.reverse();
+                }
+            }
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Keep only every M-th point of each segment of each track (and the last point of each segment).
+/// The names of the tracks get a suffix telling they were decimated (by `factor_m`).
+pub struct Decimate {
+    pub factor_m: u16,
+    /// Start keeping the points at this index of each segment, instead of the first point,
+    /// e.g. to align the decimated outputs of related files.
+    pub offset: usize,
+    /// Decimate the segments that would still have more points by a larger factor, computed for each segment
+    /// (see `Decimate::segment_factor()`), for the tools that limit the number of points per segment.
+    pub max_points_per_segment: Option<usize>,
+}
+
+impl Decimate {
+    /// Get the factor to decimate a segment by: `factor_m`,
+    /// or the smallest larger factor that leaves at most `max_points_per_segment` points in the segment.
+    pub fn segment_factor(&self, segment: &gpx::TrackSegment) -> u16 {
+        let Some(max_points) = self.max_points_per_segment else {
+            return self.factor_m;
+        };
+
+        // Decimating by `f` leaves about `len / f` points, so there is no need to try the smaller factors.
+        let estimate = segment.points.len() / max_points.max(1);
+        let mut factor_m = self
+            .factor_m
+            .max(estimate.clamp(1, u16::MAX as usize) as u16);
+        while factor_m < u16::MAX
+            && count_segment_points_after_decimation(segment, factor_m, self.offset) > max_points
+        {
+            factor_m += 1;
+        }
+
+        factor_m
+    }
+}
+
+impl Transform for Decimate {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if self.factor_m == 0 {
+            return Err(eyre::eyre!("Cannot decimate by a factor of 0"));
+        }
+
+        for track in &mut gpx.tracks {
+            track.name = track
+                .name
+                .clone()
+                .map(|name| format!("{name} ({})", Action::Decimate(self.factor_m)));
+
+            for segment in &mut track.segments {
+                let factor_m = self.segment_factor(segment);
+                decimate_segment(segment, factor_m, self.offset);
+            }
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// A small pseudo-random number generator (SplitMix64), so that the same seed always gives the same numbers,
+/// whatever the platform or the versions of the dependencies.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Get a number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        // The 53 most significant bits, which is the precision of a f64.
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Keep each point of each segment of each track with the probability `fraction` (between 0 and 1),
+/// for statistical sampling or for building test data, when the regular sampling of `Decimate` introduces aliasing.
+/// The first and last points of the segments are always kept.
+/// The same `seed` always keeps the same points.
+pub struct RandomSample {
+    pub fraction: f64,
+    pub seed: u64,
+}
+
+impl Transform for RandomSample {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if !(0.0..=1.0).contains(&self.fraction) {
+            return Err(eyre::eyre!(
+                "The fraction of points to keep should be between 0 and 1, got {}",
+                self.fraction
+            ));
+        }
+
+        let mut rng = SplitMix64(self.seed);
+
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let len = segment.points.len();
+            let keep = (0..len)
+                .map(|i| i == 0 || i + 1 == len || rng.next_f64() < self.fraction)
+                .collect::<Vec<_>>();
+
+            retain_flagged(&mut segment.points, &keep);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the spikes of the tracks: the points that are further than `max_distance_m` from both their neighbors.
+/// The first and last points of the segments are always kept.
+pub struct Despike {
+    pub max_distance_m: f64,
+}
+
+impl Transform for Despike {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let points = &segment.points;
+            let keep = (0..points.len())
+                .map(|i| {
+                    i > 0
+                        && i + 1 < points.len()
+                        && distance(&points[i - 1], &points[i]) > self.max_distance_m
+                        && distance(&points[i], &points[i + 1]) > self.max_distance_m
+                })
+                .map(|is_spike| !is_spike)
+                .collect::<Vec<_>>();
+
+            retain_flagged(&mut segment.points, &keep);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the points at Null Island (see `is_at_null_island()`) of the tracks, of the routes and the waypoints:
+/// broken recorders write `0, 0` when they have no fix.
+pub struct DropNullIsland;
+
+impl Transform for DropNullIsland {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let keep = |point: &gpx::Waypoint| !is_at_null_island(point);
+
+        gpx.waypoints.retain(keep);
+        for route in &mut gpx.routes {
+            route.points.retain(keep);
+        }
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            segment.points.retain(keep);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the track points closer than `min_distance_m` to a point kept before them, in any track or segment,
+/// to thin the clusters of near-identical points where recordings overlap (e.g. after merging them).
+/// Unlike a decimation, it only removes the points where the tracks are dense, wherever they come from.
+/// The segments left without points are removed.
+pub struct DedupPoints {
+    pub min_distance_m: f64,
+}
+
+impl Transform for DedupPoints {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        // The kept points in the cells of a grid sized from the distance (see `get_grid_step()`),
+        // so that only a few cells are searched for each point.
+        let step_deg = get_grid_step(self.min_distance_m);
+        let mut grid: HashMap<(i64, i64), Vec<gpx::Waypoint>> = HashMap::new();
+
+        for track in &mut gpx.tracks {
+            for segment in &mut track.segments {
+                segment.points.retain(|point| {
+                    let (lat, lon) = (point.point().y(), point.point().x());
+                    let is_duplicate =
+                        get_grid_cells_near((lat, lon), (lat, lon), self.min_distance_m, step_deg)
+                            .filter_map(|cell| grid.get(&cell))
+                            .flatten()
+                            .any(|kept| distance(point, kept) < self.min_distance_m);
+
+                    if !is_duplicate {
+                        grid.entry(get_grid_cell_of(lat, lon, step_deg))
+                            .or_default()
+                            .push(gpx::Waypoint::new(point.point()));
+                    }
+                    !is_duplicate
+                });
+            }
+
+            track.segments.retain(|segment| !segment.points.is_empty());
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Keep only the points whose flag is set in `keep`.
+fn retain_flagged(points: &mut Vec<gpx::Waypoint>, keep: &[bool]) {
+    let mut index = 0;
+    points.retain(|_| {
+        index += 1;
+        keep[index - 1]
+    });
+}
+
+/// Simplify the tracks with the Ramer-Douglas-Peucker algorithm: the points closer than `tolerance_m`
+/// to the simplified line are removed.
+/// The first and last points of the segments are always kept.
+pub struct SimplifyRdp {
+    pub tolerance_m: f64,
+}
+
+impl Transform for SimplifyRdp {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let points = &segment.points;
+            if points.len() <= 2 {
+                continue;
+            }
+
+            let mut keep = vec![false; points.len()];
+            keep[0] = true;
+            keep[points.len() - 1] = true;
+
+            // Ranges of points to simplify, between two kept points.
+            let mut ranges = vec![(0, points.len() - 1)];
+            while let Some((first, last)) = ranges.pop() {
+                let farthest = (first + 1..last)
+                    .map(|i| {
+                        (
+                            i,
+                            distance_to_line(&points[i], &points[first], &points[last]),
+                        )
+                    })
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+                if let Some((i, distance_m)) = farthest
+                    && distance_m > self.tolerance_m
+                {
+                    keep[i] = true;
+                    ranges.push((first, i));
+                    ranges.push((i, last));
+                }
+            }
+
+            retain_flagged(&mut segment.points, &keep);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// A point that may be removed by `SimplifyVisvalingam`, with the area of the triangle it forms with its neighbors.
+/// The ordering is reversed, so that the point with the smallest area comes first in a `BinaryHeap`.
+struct Candidate {
+    area_m2: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area_m2
+            .total_cmp(&self.area_m2)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// Simplify the tracks with the Visvalingam-Whyatt algorithm: the point forming the smallest triangle
+/// with its neighbors is removed, again and again, until the smallest triangle is at least `min_area_m2`
+/// or the segment has at most `max_points` points (whichever comes first).
+/// The first and last points of the segments are always kept.
+pub struct SimplifyVisvalingam {
+    pub min_area_m2: Option<f64>,
+    pub max_points: Option<usize>,
+}
+
+impl SimplifyVisvalingam {
+    /// Tell which points of a segment are kept.
+    fn simplify(&self, points: &[gpx::Waypoint]) -> Vec<bool> {
+        let len = points.len();
+        let mut keep = vec![true; len];
+        if len <= 2 {
+            return keep;
+        }
+
+        // The neighbors of the points that are still kept (only meaningful for them).
+        let mut previous = (0..len).map(|i| i.saturating_sub(1)).collect::<Vec<_>>();
+        let mut next = (0..len).map(|i| i + 1).collect::<Vec<_>>();
+
+        let area_of = |i: usize, previous: &[usize], next: &[usize]| {
+            triangle_area(&points[previous[i]], &points[i], &points[next[i]])
+        };
+
+        // The first and last points are never removed.
+        let mut areas = (0..len)
+            .map(|i| match i {
+                0 => f64::INFINITY,
+                i if i == len - 1 => f64::INFINITY,
+                i => area_of(i, &previous, &next),
+            })
+            .collect::<Vec<_>>();
+        let mut candidates = (1..len - 1)
+            .map(|index| Candidate {
+                area_m2: areas[index],
+                index,
+            })
+            .collect::<BinaryHeap<_>>();
+
+        let mut kept_count = len;
+        while let Some(Candidate { area_m2, index }) = candidates.pop() {
+            // Outdated: the point was removed, or its area changed when a neighbor was removed.
+            if !keep[index] || area_m2 != areas[index] {
+                continue;
+            }
+
+            if self
+                .max_points
+                .is_some_and(|max_points| kept_count <= max_points)
+                || self
+                    .min_area_m2
+                    .is_some_and(|min_area_m2| area_m2 >= min_area_m2)
+            {
+                break;
+            }
+
+            keep[index] = false;
+            kept_count -= 1;
+
+            let (before, after) = (previous[index], next[index]);
+            next[before] = after;
+            previous[after] = before;
+
+            for neighbor in [before, after] {
+                if neighbor != 0 && neighbor != len - 1 {
+                    // Never less than the area of the removed point,
+                    // so that the points are removed by increasing area.
+                    areas[neighbor] = area_of(neighbor, &previous, &next).max(area_m2);
+                    candidates.push(Candidate {
+                        area_m2: areas[neighbor],
+                        index: neighbor,
+                    });
+                }
+            }
+        }
+
+        keep
+    }
+}
+
+impl Transform for SimplifyVisvalingam {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if self.min_area_m2.is_none() && self.max_points.is_none() {
+            return Err(eyre::eyre!(
+                "Cannot simplify without a minimum area or a maximum number of points"
+            ));
+        }
+
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let keep = self.simplify(&segment.points);
+            retain_flagged(&mut segment.points, &keep);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Fill the gaps of the tracks (e.g. when the GPS signal was lost): when two consecutive points
+/// are further than `max_gap_m` from each other, points are interpolated between them, every `step_m` or so.
+/// The gaps longer than `limit_m` are left alone, since they are likely genuine breaks.
+/// Gaps between segments are not filled.
+pub struct GapFill {
+    pub max_gap_m: f64,
+    pub step_m: f64,
+    pub limit_m: Option<f64>,
+}
+
+impl Transform for GapFill {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if self.step_m.is_nan() || self.step_m <= 0.0 {
+            return Err(eyre::eyre!(
+                "The step must be strictly positive, got {}",
+                self.step_m
+            ));
+        }
+
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let mut points = Vec::with_capacity(segment.points.len());
+
+            for point in segment.points.drain(..) {
+                if let Some(previous) = points.last() {
+                    let gap_m = distance(previous, &point);
+                    let is_filled = gap_m > self.max_gap_m
+                        && self.limit_m.is_none_or(|limit_m| gap_m <= limit_m);
+
+                    if is_filled {
+                        let steps = (gap_m / self.step_m).ceil() as usize;
+                        let previous = previous.clone();
+                        points.extend((1..steps).map(|i| {
+                            interpolate_point(&previous, &point, i as f64 / steps as f64)
+                        }));
+                    }
+                }
+
+                points.push(point);
+            }
+
+            segment.points = points;
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Add a waypoint at the first point of each segment but the first of each track, named like "Segment 2 start",
+/// to show where the recording was interrupted in a map viewer.
+/// With several tracks, the names also tell the track, like "Track 1 / Segment 2 start".
+pub struct SegmentBreakMarkers;
+
+impl Transform for SegmentBreakMarkers {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let has_several_tracks = gpx.tracks.len() > 1;
+
+        for (t, track) in gpx.tracks.iter().enumerate() {
+            for (s, segment) in track.segments.iter().enumerate().skip(1) {
+                let Some(first_point) = segment.points.first() else {
+                    continue;
+                };
+
+                let mut waypoint = first_point.clone();
+                waypoint.name = Some(if has_several_tracks {
+                    format!("Track {} / Segment {} start", t + 1, s + 1)
+                } else {
+                    format!("Segment {} start", s + 1)
+                });
+                gpx.waypoints.push(waypoint);
+            }
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Add a waypoint at every `every`-th track point, named like "point #100" after the index of the point
+/// in the whole data (counting from 1), to find the region of a track by the index of its points.
+pub struct CheckpointMarkers {
+    pub every: usize,
+}
+
+impl Transform for CheckpointMarkers {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if self.every == 0 {
+            return Err(eyre::eyre!(
+                "The number of points between checkpoints should be positive"
+            ));
+        }
+
+        let checkpoints = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .flat_map(|segment| &segment.points)
+            .enumerate()
+            .map(|(i, point)| (i + 1, point))
+            .filter(|(n, _)| n % self.every == 0)
+            .map(|(n, point)| {
+                let mut waypoint = point.clone();
+                waypoint.name = Some(format!("point #{n}"));
+                waypoint
+            })
+            .collect::<Vec<_>>();
+
+        gpx.waypoints.extend(checkpoints);
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Add waypoints named "Start" and "Finish" at the first and last track points of the whole data,
+/// to label the ends of a route in route sheets and map viewers. Nothing is added when there are no track points.
+pub struct StartFinishMarkers;
+
+impl Transform for StartFinishMarkers {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let mut points = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .flat_map(|segment| &segment.points);
+
+        let markers = match (points.next(), points.last()) {
+            (Some(first), last) => [(first, "Start"), (last.unwrap_or(first), "Finish")]
+                .map(|(point, name)| {
+                    let mut waypoint = point.clone();
+                    waypoint.name = Some(name.to_owned());
+                    waypoint
+                })
+                .to_vec(),
+            (None, _) => Vec::new(),
+        };
+
+        gpx.waypoints.extend(markers);
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Set the speed of the track points (in meters per second), from the distance and the time to the previous point
+/// (or to the next one, for the first point of a segment).
+/// The points without times, or whose neighbor has the same time or no time, get no speed.
+/// The times may decrease, e.g. in inverted files (see `Invert`): the durations are taken in absolute value.
+///
+/// The `gpx` crate does not write the speeds; see `WriteOptions::add_speed` to write them as extensions.
+pub struct AddSpeed;
+
+impl Transform for AddSpeed {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let speed_between = |a: &gpx::Waypoint, b: &gpx::Waypoint| {
+            let duration = (OffsetDateTime::from(b.time?) - OffsetDateTime::from(a.time?)).abs();
+            duration
+                .is_positive()
+                .then(|| distance(a, b) / duration.as_seconds_f64())
+        };
+
+        for segment in gpx.tracks.iter_mut().flat_map(|track| &mut track.segments) {
+            let speeds = (0..segment.points.len())
+                .map(|i| match i {
+                    0 => segment
+                        .points
+                        .get(1)
+                        .and_then(|next| speed_between(&segment.points[0], next)),
+                    i => speed_between(&segment.points[i - 1], &segment.points[i]),
+                })
+                .collect::<Vec<_>>();
+
+            for (point, speed) in segment.points.iter_mut().zip(speeds) {
+                point.speed = speed;
+            }
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove personal data: the metadata, the sources (recording devices), the times and optionally the elevations.
+pub struct Anonymize {
+    pub keep_elevation: bool,
+}
+
+impl Transform for Anonymize {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        // The metadata contains the author, the links, the time...
+        gpx.metadata = None;
+
+        // The source is usually the device that recorded the data.
+        gpx.tracks.iter_mut().for_each(|track| track.source = None);
+        gpx.routes.iter_mut().for_each(|route| route.source = None);
+
+        for_each_point_mut(gpx, |point| {
+            point.time = None;
+            point.source = None;
+
+            if !self.keep_elevation {
+                point.elevation = None;
+            }
+        });
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the elevations and/or the times of all the points.
+/// It is lighter than `Anonymize`, which also removes the metadata and the sources.
+pub struct Strip {
+    pub elevation: bool,
+    pub time: bool,
+}
+
+impl Transform for Strip {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for_each_point_mut(gpx, |point| {
+            if self.elevation {
+                point.elevation = None;
+            }
+            if self.time {
+                point.time = None;
+            }
+        });
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Round the elevations of all the points to `digits` decimals, since the elevations from digital elevation models
+/// often have many meaningless decimals, which bloat the files.
+pub struct RoundElevation {
+    pub digits: u8,
+}
+
+impl Transform for RoundElevation {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let scale = 10f64.powi(i32::from(self.digits));
+        for_each_point_mut(gpx, |point| {
+            point.elevation = point
+                .elevation
+                .map(|elevation| (elevation * scale).round() / scale);
+        });
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Convert each route into a track with a single segment, after the existing tracks.
+pub struct RouteToTrack;
+
+impl Transform for RouteToTrack {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        gpx.tracks
+            .extend(gpx.routes.drain(..).map(route_into_track));
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Convert each track into a route, after the existing routes.
+/// The points of all the segments of a track are put in its route.
+pub struct TrackToRoute;
+
+impl Transform for TrackToRoute {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        gpx.routes
+            .extend(gpx.tracks.drain(..).map(track_into_route));
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Shift the times of all the points (waypoints, route points and track points) and of the metadata
+/// by the same duration, which preserves their spacing. The points without times stay without times.
+pub struct ShiftTime {
+    pub offset: time::Duration,
+}
+
+impl Transform for ShiftTime {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let shift = |time: &mut Option<gpx::Time>| {
+            *time = time.map(|time| (OffsetDateTime::from(time) + self.offset).into());
+        };
+
+        for_each_point_mut(gpx, |point| shift(&mut point.time));
+        if let Some(metadata) = &mut gpx.metadata {
+            shift(&mut metadata.time);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Shift the times of all the points (see `ShiftTime`) so that the earliest one becomes `start`,
+/// e.g. to hide the dates of activities, or to compare them on a common timeline.
+/// Nothing changes when no points have times.
+pub struct RebaseTime {
+    pub start: OffsetDateTime,
+}
+
+impl Transform for RebaseTime {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let mut earliest: Option<OffsetDateTime> = None;
+        for_each_point_mut(gpx, |point| {
+            if let Some(time) = point.time.map(OffsetDateTime::from) {
+                earliest = Some(earliest.map_or(time, |earliest| earliest.min(time)));
+            }
+        });
+
+        match earliest {
+            Some(earliest) => ShiftTime {
+                offset: self.start - earliest,
+            }
+            .apply(gpx),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The track points to keep with `Extract`.
+#[derive(Clone, Copy, Debug)]
+pub enum PointRange {
+    /// The points between two indices (included), counting the points of all the segments of all the tracks from 0.
+    /// The points from the first one, or until the last one, when an index is not set.
+    Indices {
+        start: Option<usize>,
+        end: Option<usize>,
+    },
+    /// The points whose times are between two times (included); the points without times are removed.
+    /// The points from the first one, or until the last one, when a time is not set.
+    Times {
+        after: Option<OffsetDateTime>,
+        before: Option<OffsetDateTime>,
+    },
+}
+
+/// Only keep the track points in a range, e.g. to carve out the climb of a ride.
+/// Segments without points are removed, but tracks are kept (even without segments):
+/// apply `PruneEmptyTracks` afterwards to remove them.
+/// The routes and the waypoints are kept.
+pub struct Extract {
+    pub range: PointRange,
+}
+
+impl Transform for Extract {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let count = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| segment.points.len())
+            .sum::<usize>();
+
+        let mut index = 0;
+        let mut keep: Box<dyn FnMut(&gpx::Waypoint) -> bool> = match self.range {
+            PointRange::Indices { start, end } => {
+                let start = start.unwrap_or(0);
+                let end = end.unwrap_or(count.saturating_sub(1));
+                for index in [start, end] {
+                    if index >= count {
+                        return Err(eyre::eyre!(
+                            "The index {index} is out of range, since there are {count} track point(s)"
+                        ));
+                    }
+                }
+                if start > end {
+                    return Err(eyre::eyre!(
+                        "The start index {start} is after the end index {end}"
+                    ));
+                }
+
+                Box::new(move |_| {
+                    index += 1;
+                    (start..=end).contains(&(index - 1))
+                })
+            }
+            PointRange::Times { after, before } => Box::new(move |point| {
+                point.time.is_some_and(|time| {
+                    let time = OffsetDateTime::from(time);
+                    after.is_none_or(|after| time >= after)
+                        && before.is_none_or(|before| time <= before)
+                })
+            }),
+        };
+
+        for track in &mut gpx.tracks {
+            for segment in &mut track.segments {
+                segment.points.retain(|point| keep(point));
+            }
+            track.segments.retain(|segment| !segment.points.is_empty());
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Make a track of each segment of each track, for the tools that ignore segments but show each track apart.
+/// The new tracks keep the name, description, links... of their track, the name being followed by the number
+/// of the segment (counting from 1) when the track has several segments, like "Morning ride - segment 2".
+pub struct SegmentsToTracks;
+
+impl SegmentsToTracks {
+    /// Get the number of tracks made from each track, in order.
+    pub fn track_counts(gpx: &gpx::Gpx) -> Vec<usize> {
+        gpx.tracks
+            .iter()
+            .map(|track| track.segments.len().max(1))
+            .collect()
+    }
+}
+
+impl Transform for SegmentsToTracks {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        let mut tracks = Vec::new();
+
+        for mut track in gpx.tracks.drain(..) {
+            let segments = std::mem::take(&mut track.segments);
+            if segments.len() <= 1 {
+                // A track without segments is kept too, as the commands that cannot process it do.
+                track.segments = segments;
+                tracks.push(track);
+                continue;
+            }
+
+            for (s, segment) in segments.into_iter().enumerate() {
+                let mut new_track = track.clone();
+                new_track.name = Some(match &track.name {
+                    Some(name) => format!("{name} - segment {}", s + 1),
+                    None => format!("Segment {}", s + 1),
+                });
+                new_track.segments = vec![segment];
+                tracks.push(new_track);
+            }
+        }
+
+        gpx.tracks = tracks;
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the points outside a bounding box.
+///
+/// When a track leaves the box and comes back, its segment is split in two,
+/// so that no straight line is drawn between the exit and the entry points.
+/// Segments without points are removed, but tracks are kept (even without segments):
+/// apply `PruneEmptyTracks` afterwards to remove them.
+pub struct Crop {
+    pub bbox: BoundingBox,
+}
+
+impl Transform for Crop {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        retain_points(gpx, |point| self.bbox.contains(point));
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Only keep the points (of the tracks, of the routes and the waypoints) for which `keep` is true.
+///
+/// The segments are split where points are removed, so that no straight line is drawn over them.
+/// Segments without points are removed, but tracks are kept (even without segments).
+fn retain_points(gpx: &mut gpx::Gpx, keep: impl Fn(&gpx::Waypoint) -> bool) {
+    for track in &mut gpx.tracks {
+        let mut segments = Vec::new();
+
+        for segment in track.segments.drain(..) {
+            let mut current = gpx::TrackSegment::new();
+
+            for point in segment.points {
+                if keep(&point) {
+                    current.points.push(point);
+                } else if !current.points.is_empty() {
+                    segments.push(std::mem::take(&mut current));
+                }
+            }
+
+            if !current.points.is_empty() {
+                segments.push(current);
+            }
+        }
+
+        track.segments = segments;
+    }
+
+    for route in &mut gpx.routes {
+        route.points.retain(&keep);
+    }
+
+    gpx.waypoints.retain(&keep);
+}
+
+/// Remove the points farther than `tolerance_m` from the tracks and routes of `reference`,
+/// e.g. to clip a long recording down to the section that follows a known trail.
+///
+/// Like `Crop`, the segments are split where points are removed, and segments without points are removed.
+pub struct NearRoute {
+    pub reference: gpx::Gpx,
+    pub tolerance_m: f64,
+}
+
+impl Transform for NearRoute {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        if self.tolerance_m.is_nan() || self.tolerance_m < 0.0 {
+            return Err(eyre::eyre!(
+                "The tolerance should be a positive distance, got {}",
+                self.tolerance_m
+            ));
+        }
+
+        let polylines = self
+            .reference
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| segment.points.as_slice())
+            .chain(
+                self.reference
+                    .routes
+                    .iter()
+                    .map(|route| route.points.as_slice()),
+            );
+        let index = LineIndex::new(polylines, self.tolerance_m);
+
+        retain_points(gpx, |point| index.is_near(point));
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the tracks without points, and the segments without points of the other tracks.
+///
+/// It is meant to run after the transformations that remove points, such as `Crop`,
+/// since empty tracks clutter the output and some tools choke on them.
+pub struct PruneEmptyTracks;
+
+impl Transform for PruneEmptyTracks {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for track in &mut gpx.tracks {
+            track.segments.retain(|segment| !segment.points.is_empty());
+        }
+        gpx.tracks.retain(|track| !track.segments.is_empty());
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// Remove the segments with fewer than `min_points` points, such as the leftovers of a decimation.
+/// The tracks are kept, even if they have no segments left.
+pub struct PruneShortSegments {
+    pub min_points: usize,
+}
+
+impl Transform for PruneShortSegments {
+    fn apply(&self, gpx: &mut gpx::Gpx) -> eyre::Result<()> {
+        for track in &mut gpx.tracks {
+            track
+                .segments
+                .retain(|segment| segment.points.len() >= self.min_points);
+        }
+
+        gpx.creator = Some(get_creator());
+        Ok(())
+    }
+}
+
+/// The transformations accepted by `parse_pipeline()`, with their arguments (optional ones are in brackets).
+pub const TRANSFORM_NAMES: [&str; 12] = [
+    "anonymize[:keep-elevation]",
+    "checkpoint-every:N",
+    "crop:bbox=MIN_LAT,MIN_LON,MAX_LAT,MAX_LON",
+    "decimate:M",
+    "despike:METERS",
+    "drop-null-island",
+    "invert[:segments-only]",
+    "route-to-track",
+    "segment-break-markers",
+    "segments-to-tracks",
+    "start-finish-markers",
+    "track-to-route",
+];
+
+/// Parse the argument of a transformation, failing with a message describing the expected argument.
+fn parse_argument<T: std::str::FromStr>(
+    name: &str,
+    argument: Option<&str>,
+    expected: &str,
+) -> eyre::Result<T> {
+    argument
+        .and_then(|argument| argument.parse().ok())
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "Invalid argument for '{name}': expected {expected}, got '{}'",
+                argument.unwrap_or_default()
+            )
+        })
+}
+
+/// Parse the bounding box of a crop, written `bbox=MIN_LAT,MIN_LON,MAX_LAT,MAX_LON`.
+/// A box with `MIN_LON` greater than `MAX_LON` crosses the antimeridian.
+fn parse_bbox(argument: Option<&str>) -> eyre::Result<BoundingBox> {
+    const EXPECTED: &str = "'bbox=MIN_LAT,MIN_LON,MAX_LAT,MAX_LON'";
+
+    let values = argument
+        .and_then(|argument| argument.strip_prefix("bbox="))
+        .map(|values| {
+            values
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+    match values {
+        Some(Ok(values)) if values.len() == 4 => Ok(BoundingBox {
+            min_lat: values[0],
+            min_lon: values[1],
+            max_lat: values[2],
+            max_lon: values[3],
+        }),
+        _ => Err(eyre::eyre!(
+            "Invalid argument for 'crop': expected {EXPECTED}, got '{}'",
+            argument.unwrap_or_default()
+        )),
+    }
+}
+
+/// Parse a flag argument of a transformation, which is either absent or `flag`.
+fn parse_flag(name: &str, argument: Option<&str>, flag: &str) -> eyre::Result<bool> {
+    match argument {
+        None => Ok(false),
+        Some(argument) if argument == flag => Ok(true),
+        Some(argument) => Err(eyre::eyre!(
+            "Invalid argument for '{name}': expected nothing or '{flag}', got '{argument}'"
+        )),
+    }
+}
+
+/// Parse a pipeline of transformations, written as a semicolon-separated list of `NAME[:ARGUMENT]`.
+///
+/// The transformations are listed in `TRANSFORM_NAMES`.
+///
+/// For instance: `crop:bbox=45,5,46,6;decimate:10`.
+pub fn parse_pipeline(pipeline: &str) -> eyre::Result<Vec<Box<dyn Transform>>> {
+    let steps = pipeline
+        .split(';')
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+        .collect::<Vec<_>>();
+
+    if steps.is_empty() {
+        return Err(eyre::eyre!("The pipeline has no transformations"));
+    }
+
+    steps
+        .into_iter()
+        .map(|step| {
+            let (name, argument) = match step.split_once(':') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (step, None),
+            };
+
+            let transform: Box<dyn Transform> = match name {
+                "anonymize" => Box::new(Anonymize {
+                    keep_elevation: parse_flag(name, argument, "keep-elevation")?,
+                }),
+                "checkpoint-every" => {
+                    let expected = "a positive number of points";
+                    let every = parse_argument(name, argument, expected)?;
+                    if every == 0 {
+                        return Err(eyre::eyre!(
+                            "Invalid argument for '{name}': expected {expected}, got '0'"
+                        ));
+                    }
+                    Box::new(CheckpointMarkers { every })
+                }
+                "crop" => Box::new(Crop {
+                    bbox: parse_bbox(argument)?,
+                }),
+                "decimate" => {
+                    let expected = "a factor between 1 and 65535";
+                    let factor_m = parse_argument(name, argument, expected)?;
+                    if factor_m == 0 {
+                        return Err(eyre::eyre!(
+                            "Invalid argument for '{name}': expected {expected}, got '0'"
+                        ));
+                    }
+                    Box::new(Decimate {
+                        factor_m,
+                        offset: 0,
+                        max_points_per_segment: None,
+                    })
+                }
+                "despike" => Box::new(Despike {
+                    max_distance_m: parse_argument(name, argument, "a distance in meters")?,
+                }),
+                "invert" => Box::new(Invert {
+                    segments_only: parse_flag(name, argument, "segments-only")?,
+                }),
+                "drop-null-island"
+                | "route-to-track"
+                | "segment-break-markers"
+                | "segments-to-tracks"
+                | "start-finish-markers"
+                | "track-to-route" => {
+                    if let Some(argument) = argument {
+                        return Err(eyre::eyre!(
+                            "Invalid argument for '{name}': expected nothing, got '{argument}'"
+                        ));
+                    }
+                    match name {
+                        "drop-null-island" => Box::new(DropNullIsland),
+                        "route-to-track" => Box::new(RouteToTrack),
+                        "segment-break-markers" => Box::new(SegmentBreakMarkers),
+                        "segments-to-tracks" => Box::new(SegmentsToTracks),
+                        "start-finish-markers" => Box::new(StartFinishMarkers),
+                        _ => Box::new(TrackToRoute),
+                    }
+                }
+                _ => {
+                    return Err(eyre::eyre!(
+                        "Unknown transformation '{name}', expected one of: {}",
+                        TRANSFORM_NAMES.join(", ")
+                    ));
+                }
+            };
+
+            Ok(transform)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{assert_few_cells_are_searched, make_gpx, make_track};
+
+    /// Get the points of the segments of the tracks, as (latitude, longitude).
+    fn get_points(gpx: &gpx::Gpx) -> Vec<Vec<(f64, f64)>> {
+        gpx.tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| {
+                segment
+                    .points
+                    .iter()
+                    .map(|point| (point.point().y(), point.point().x()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn near_route_keeps_the_points_within_the_tolerance() {
+        let reference = make_gpx(vec![make_track("Trail", &[&[(45.0, 5.0), (45.0, 5.01)]])]);
+        let mut gpx = make_gpx(vec![make_track(
+            "Ride",
+            &[&[(45.0002, 5.002), (45.01, 5.005), (45.0002, 5.008)]],
+        )]);
+
+        NearRoute {
+            reference,
+            tolerance_m: 50.0,
+        }
+        .apply(&mut gpx)
+        .unwrap();
+
+        // Split where the far point was removed.
+        assert_eq!(
+            get_points(&gpx),
+            [vec![(45.0002, 5.002)], vec![(45.0002, 5.008)]]
+        );
+    }
+
+    #[test]
+    fn near_route_works_with_a_huge_tolerance() {
+        let points = [
+            (45.0, 5.0),
+            (45.1, 5.1),
+            (45.2, 5.2),
+            (45.3, 5.3),
+            (45.4, 5.4),
+        ];
+        let reference = make_gpx(vec![make_track("Trail", &[&points])]);
+        let mut gpx = make_gpx(vec![make_track(
+            "Ride",
+            &[&points.map(|(lat, lon)| (lat + 1.0, lon))],
+        )]);
+
+        NearRoute {
+            reference,
+            tolerance_m: 200_000.0,
+        }
+        .apply(&mut gpx)
+        .unwrap();
+
+        assert_eq!(get_points(&gpx)[0].len(), 5);
+        assert_few_cells_are_searched(200_000.0);
+    }
+
+    #[test]
+    fn dedup_points_removes_the_points_close_to_a_kept_point() {
+        let mut gpx = make_gpx(vec![
+            make_track("First", &[&[(45.0, 5.0), (45.001, 5.0)]]),
+            // The first point is about 1 meter from the first point of the first track.
+            make_track("Second", &[&[(45.00001, 5.0)], &[(45.002, 5.0)]]),
+        ]);
+
+        DedupPoints {
+            min_distance_m: 5.0,
+        }
+        .apply(&mut gpx)
+        .unwrap();
+
+        // The segment left without points is removed.
+        assert_eq!(
+            get_points(&gpx),
+            [vec![(45.0, 5.0), (45.001, 5.0)], vec![(45.002, 5.0)]]
+        );
+    }
+
+    #[test]
+    fn dedup_points_works_with_a_huge_distance() {
+        let points = [
+            (45.0, 5.0),
+            (45.1, 5.1),
+            (45.2, 5.2),
+            (45.3, 5.3),
+            (45.4, 5.4),
+        ];
+        let mut gpx = make_gpx(vec![make_track("Ride", &[&points, &points])]);
+
+        DedupPoints {
+            min_distance_m: 200_000.0,
+        }
+        .apply(&mut gpx)
+        .unwrap();
+
+        assert_eq!(get_points(&gpx), [vec![(45.0, 5.0)]]);
+        assert_few_cells_are_searched(200_000.0);
+    }
+
+    #[test]
+    fn add_speed_works_with_decreasing_times() {
+        let mut gpx = make_gpx(vec![make_track(
+            "Inverted ride",
+            &[&[(45.0, 5.0), (45.001, 5.0), (45.002, 5.0)]],
+        )]);
+        let end = OffsetDateTime::from_unix_timestamp(1_717_228_800).unwrap();
+        for (i, point) in gpx.tracks[0].segments[0].points.iter_mut().enumerate() {
+            point.time = Some((end - time::Duration::seconds(10 * i as i64)).into());
+        }
+
+        AddSpeed.apply(&mut gpx).unwrap();
+
+        // About 111 m in 10 s between the points.
+        for point in &gpx.tracks[0].segments[0].points {
+            let speed = point.speed.expect("Every point has a speed");
+            assert!((speed - 11.1).abs() < 0.1, "{speed}");
+        }
+    }
+
+    #[test]
+    fn random_sample_keeps_the_same_points_for_the_same_seed() {
+        let points = (0..50)
+            .map(|i| (45.0 + f64::from(i) * 0.001, 5.0))
+            .collect::<Vec<_>>();
+        let sample = |fraction: f64, seed: u64| {
+            let mut gpx = make_gpx(vec![make_track("Ride", &[&points])]);
+            RandomSample { fraction, seed }.apply(&mut gpx).unwrap();
+            get_points(&gpx).remove(0)
+        };
+
+        let kept = sample(0.3, 42);
+        assert_eq!(kept, sample(0.3, 42));
+        assert_ne!(kept, sample(0.3, 43));
+        assert!(
+            kept.len() > 2 && kept.len() < points.len(),
+            "{}",
+            kept.len()
+        );
+        assert_eq!(kept.first(), points.first());
+        assert_eq!(kept.last(), points.last());
+
+        // Only the first and last points, whatever the seed.
+        assert_eq!(sample(0.0, 7), [points[0], points[49]]);
+    }
+
+    /// A zig-zag segment along a parallel: the second point is about 1 meter off the line,
+    /// the fourth one about 111 meters.
+    const ZIG_ZAG: [(f64, f64); 6] = [
+        (45.0, 5.0),
+        (45.00001, 5.001),
+        (45.0, 5.002),
+        (45.001, 5.003),
+        (45.0, 5.004),
+        (45.0, 5.005),
+    ];
+
+    /// Simplify `ZIG_ZAG`, and get the indices of the kept points.
+    fn simplify_zig_zag(simplify: impl Transform) -> Vec<usize> {
+        let mut gpx = make_gpx(vec![make_track("Ride", &[&ZIG_ZAG])]);
+        simplify.apply(&mut gpx).unwrap();
+        get_points(&gpx)[0]
+            .iter()
+            .map(|point| ZIG_ZAG.iter().position(|p| p == point).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn simplify_rdp_removes_the_points_within_the_tolerance() {
+        assert_eq!(
+            simplify_zig_zag(SimplifyRdp { tolerance_m: 10.0 }),
+            [0, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            simplify_zig_zag(SimplifyRdp { tolerance_m: 0.1 }),
+            [0, 1, 2, 3, 4, 5]
+        );
+        // The ends are always kept.
+        assert_eq!(
+            simplify_zig_zag(SimplifyRdp {
+                tolerance_m: 1000.0
+            }),
+            [0, 5]
+        );
+    }
+
+    #[test]
+    fn simplify_visvalingam_removes_the_smallest_triangles() {
+        let by_area = |min_area_m2| SimplifyVisvalingam {
+            min_area_m2: Some(min_area_m2),
+            max_points: None,
+        };
+        assert_eq!(simplify_zig_zag(by_area(1000.0)), [0, 2, 3, 4, 5]);
+        assert_eq!(simplify_zig_zag(by_area(1.0)), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(simplify_zig_zag(by_area(1e9)), [0, 5]);
+
+        let by_count = |max_points| SimplifyVisvalingam {
+            min_area_m2: None,
+            max_points: Some(max_points),
+        };
+        // The point with the largest triangle is the last one removed.
+        assert_eq!(simplify_zig_zag(by_count(3)), [0, 3, 5]);
+        assert_eq!(simplify_zig_zag(by_count(5)), [0, 2, 3, 4, 5]);
+        // The ends are always kept.
+        assert_eq!(simplify_zig_zag(by_count(0)), [0, 5]);
+    }
+
+    /// Parse a pipeline that must be invalid, and get the error message.
+    fn parse_pipeline_error(pipeline: &str) -> String {
+        match parse_pipeline(pipeline) {
+            Ok(_) => panic!("the pipeline '{pipeline}' should be invalid"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_pipeline_accepts_every_transformation() {
+        let transforms = parse_pipeline(
+            "anonymize:keep-elevation; checkpoint-every:5; crop:bbox=45,5,46,6; decimate:10; despike:500; \
+             drop-null-island; invert; route-to-track; segment-break-markers; segments-to-tracks; \
+             start-finish-markers; track-to-route;",
+        )
+        .unwrap();
+        assert_eq!(transforms.len(), TRANSFORM_NAMES.len());
+    }
+
+    #[test]
+    fn parse_pipeline_reports_the_invalid_steps() {
+        assert_eq!(
+            parse_pipeline_error(" ; "),
+            "The pipeline has no transformations"
+        );
+        assert_eq!(
+            parse_pipeline_error("decimate:10;inverse"),
+            format!(
+                "Unknown transformation 'inverse', expected one of: {}",
+                TRANSFORM_NAMES.join(", ")
+            )
+        );
+        assert_eq!(
+            parse_pipeline_error("decimate:0"),
+            "Invalid argument for 'decimate': expected a factor between 1 and 65535, got '0'"
+        );
+        assert_eq!(
+            parse_pipeline_error("decimate"),
+            "Invalid argument for 'decimate': expected a factor between 1 and 65535, got ''"
+        );
+        assert_eq!(
+            parse_pipeline_error("crop:bbox=45,5,46"),
+            "Invalid argument for 'crop': expected 'bbox=MIN_LAT,MIN_LON,MAX_LAT,MAX_LON', got 'bbox=45,5,46'"
+        );
+        assert_eq!(
+            parse_pipeline_error("crop:45,5,46,6"),
+            "Invalid argument for 'crop': expected 'bbox=MIN_LAT,MIN_LON,MAX_LAT,MAX_LON', got '45,5,46,6'"
+        );
+        assert_eq!(
+            parse_pipeline_error("drop-null-island:x"),
+            "Invalid argument for 'drop-null-island': expected nothing, got 'x'"
+        );
+        assert_eq!(
+            parse_pipeline_error("invert:points-only"),
+            "Invalid argument for 'invert': expected nothing or 'segments-only', got 'points-only'"
+        );
+    }
+}